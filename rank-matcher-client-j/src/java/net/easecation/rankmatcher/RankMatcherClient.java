@@ -22,6 +22,7 @@ import net.easecation.rankmatcher.network.MessageCodec;
 import net.easecation.rankmatcher.network.MessageHandler;
 
 import java.net.URI;
+import java.util.ArrayList;
 import java.util.List;
 import java.util.Map;
 
@@ -98,10 +99,20 @@ public class RankMatcherClient {
     // 业务API ============
 
     public void addArena(String arenaName, int numPlayers) {
-        Message msg = AddArenaMessage.of(arenaName, numPlayers);
+        addArena(arenaName, numPlayers, 1);
+    }
+
+    public void addArena(String arenaName, int numPlayers, int numTeams) {
+        addArena(AddArenaMessage.of(arenaName, numPlayers, numTeams));
+    }
+
+    /*
+     * 需要设置更多匹配池参数时，先构造AddArenaMessage再调用这个方法
+     * */
+    public void addArena(AddArenaMessage msg) {
         sender.sendAsyncMessage(msg, f -> {
             if (!f.isSuccess()) {
-                log.warn("添加竞技场失败: " + arenaName, f.cause());
+                log.warn("添加竞技场失败: " + msg.getArena(), f.cause());
             }
         });
     }
@@ -111,16 +122,65 @@ public class RankMatcherClient {
         sender.sendAsyncMessage(msg, f -> {});
     }
 
-    public void addPlayer(String arenaName, String playerName, int score, int length, int initRankDiff, int speed) {
+    public void addPlayer(String arenaName, String playerName, double score, int length, double initRankDiff, double speed) {
         Message msg = AddPlayerMessage.of(arenaName, playerName, score, length, initRankDiff, speed);
         sender.sendAsyncMessage(msg, f -> {});
     }
 
+    /*
+     * 需要设置角色等更多玩家参数时，先构造AddPlayerMessage再调用这个方法
+     * */
+    public void addPlayer(AddPlayerMessage msg) {
+        sender.sendAsyncMessage(msg, f -> {});
+    }
+
     public void removePlayer(String arenaName, String playerName) {
         Message msg = RemovePlayerMessage.of(arenaName, playerName);
         sender.sendAsyncMessage(msg, f -> {});
     }
 
+    /*
+     * 修改排队中的玩家，已经扩大的区间和等待时间保持不变。需要设置角色时先构造UpdatePlayerMessage再调用下面的方法
+     * */
+    public void updatePlayer(String arenaName, String playerName, double rank, int length, double speed) {
+        updatePlayer(UpdatePlayerMessage.of(arenaName, playerName, rank, length, speed));
+    }
+
+    public void updatePlayer(UpdatePlayerMessage msg) {
+        sender.sendAsyncMessage(msg, f -> {});
+    }
+
+    /*
+     * 报告一局的比赛结果，placements是每个队伍的名次，0表示第一名。不分队的匹配池是每个小队的名次，按匹配成功时的顺序
+     * */
+    public void reportMatchResult(int stageRequestId, List<Integer> placements) {
+        Message msg = MatchResultMessage.of(stageRequestId, placements);
+        sender.sendAsyncMessage(msg, f -> {});
+    }
+
+    /*
+     * 已经开始的房间有玩家离开时请求补位，openSlots是每个队伍还缺几个人。需要设置地区或属性时先构造BackfillMessage再调用下面的方法
+     * */
+    public void backfill(String arenaName, int stageRequestId, double rank, double rankDiff, double speed, List<Integer> openSlots) {
+        backfill(BackfillMessage.of(arenaName, stageRequestId, rank, rankDiff, speed, openSlots));
+    }
+
+    public void backfill(BackfillMessage msg) {
+        sender.sendAsyncMessage(msg, f -> {});
+    }
+
+    /*
+     * 回复PendingMatchMessage，所有小队都接受以后才会创建房间
+     * */
+    public void acceptMatch(int pendingId, String playerName, boolean accepted) {
+        Message msg = AcceptMatchMessage.of(pendingId, playerName, accepted);
+        sender.sendAsyncMessage(msg, f -> {});
+    }
+
+    public void cancelBackfill(String arenaName, int stageRequestId) {
+        backfill(arenaName, stageRequestId, 0, 0, 0, new ArrayList<>());
+    }
+
     public void getOrSubscribeState(int period) {
         Message msg = GetOrSubscribeStateMessage.of(period);
         sender.sendAsyncMessage(msg, f -> {});
@@ -128,7 +188,7 @@ public class RankMatcherClient {
 
     public void registerMatchSuccessHandler(MatchSuccessHandler handler) {
         this.receiver.addHandler(MessageType.MATCH_SUCCESS, MatchSuccessMessage.class, msg -> {
-            handler.onMatchSuccess(msg.getArena(), msg.getStageRequestId(), msg.getPlayers());
+            handler.onMatchSuccess(msg.getArena(), msg.getStageRequestId(), msg.getPlayers(), msg.getQuality(), msg.getBots(), msg.getAttributes());
         });
     }
 
@@ -138,22 +198,91 @@ public class RankMatcherClient {
         });
     }
 
+    public void registerUpdatePlayerFailureHandler(UpdatePlayerFailureHandler handler) {
+        this.receiver.addHandler(MessageType.UPDATE_PLAYER_FAILURE, UpdatePlayerFailureMessage.class, msg -> {
+            handler.onUpdatePlayerFailure(msg.getArena(), msg.getPlayer(), msg.getErrorMessage());
+        });
+    }
+
     public void registerConnectionStateHandler(ConnectionStateHandler handler) {
         this.receiver.addHandler(MessageType.CONNECTION_STATE, ConnectionStateMessage.class, msg -> {
             handler.onConnectionState(msg.getPlayerInfo());
         });
     }
 
+    /*
+     * 玩家同时在多个匹配池排队时，在其中一个匹配池匹配成功以后，其他匹配池会删除这位玩家并通知添加它的大厅服务器
+     * */
+    public void registerDequeuedHandler(DequeuedHandler handler) {
+        this.receiver.addHandler(MessageType.DEQUEUED, DequeuedMessage.class, msg -> {
+            handler.onDequeued(msg.getArena(), msg.getPlayer(), msg.getMatchedArena());
+        });
+    }
+
+    public void registerBackfillSuccessHandler(BackfillSuccessHandler handler) {
+        this.receiver.addHandler(MessageType.BACKFILL_SUCCESS, BackfillSuccessMessage.class, msg -> {
+            handler.onBackfillSuccess(msg.getArena(), msg.getStageRequestId(), msg.getPlayers());
+        });
+    }
+
+    public void registerPendingMatchHandler(PendingMatchHandler handler) {
+        this.receiver.addHandler(MessageType.PENDING_MATCH, PendingMatchMessage.class, msg -> {
+            handler.onPendingMatch(msg.getArena(), msg.getPendingId(), msg.getPlayers(), msg.getTimeoutSecs());
+        });
+    }
+
+    public void registerMatchCancelledHandler(MatchCancelledHandler handler) {
+        this.receiver.addHandler(MessageType.MATCH_CANCELLED, MatchCancelledMessage.class, msg -> {
+            handler.onMatchCancelled(msg.getArena(), msg.getPendingId(), msg.getRequeued(), msg.getRemoved());
+        });
+    }
+
     public interface MatchSuccessHandler {
-        void onMatchSuccess(String arenaName, int stageRequestId, List<Tuple<String, Integer>> playerNames);
+        void onMatchSuccess(String arenaName, int stageRequestId, List<MatchSuccessMessage.MatchedParty> players);
+
+        // 需要对局质量时重写这个方法
+        default void onMatchSuccess(String arenaName, int stageRequestId, List<MatchSuccessMessage.MatchedParty> players, MatchSuccessMessage.MatchQuality quality) {
+            onMatchSuccess(arenaName, stageRequestId, players);
+        }
+
+        // 匹配池使用机器人补位时重写这个方法
+        default void onMatchSuccess(String arenaName, int stageRequestId, List<MatchSuccessMessage.MatchedParty> players, MatchSuccessMessage.MatchQuality quality, List<MatchSuccessMessage.MatchedBot> bots) {
+            onMatchSuccess(arenaName, stageRequestId, players, quality);
+        }
+
+        // 匹配池规定了属性时重写这个方法，attributes是这一局取得一致的属性
+        default void onMatchSuccess(String arenaName, int stageRequestId, List<MatchSuccessMessage.MatchedParty> players, MatchSuccessMessage.MatchQuality quality, List<MatchSuccessMessage.MatchedBot> bots, Map<String, String> attributes) {
+            onMatchSuccess(arenaName, stageRequestId, players, quality, bots);
+        }
     }
 
     public interface MatchFailureHandler {
         void onMatchFailure(String arenaName, int errorId, String errorMessage, List<Tuple<String, Integer>> playerNames);
     }
 
+    public interface BackfillSuccessHandler {
+        void onBackfillSuccess(String arenaName, int stageRequestId, List<MatchSuccessMessage.MatchedParty> players);
+    }
+
+    public interface PendingMatchHandler {
+        void onPendingMatch(String arenaName, int pendingId, List<MatchSuccessMessage.MatchedParty> players, int timeoutSecs);
+    }
+
+    public interface MatchCancelledHandler {
+        // requeued是已经接受、回到匹配池继续等待的小队，removed是拒绝或者没有回复、已经移出匹配池的小队
+        void onMatchCancelled(String arenaName, int pendingId, List<String> requeued, List<String> removed);
+    }
+
+    public interface UpdatePlayerFailureHandler {
+        void onUpdatePlayerFailure(String arenaName, String playerName, String errorMessage);
+    }
+
+    public interface DequeuedHandler {
+        void onDequeued(String arenaName, String playerName, String matchedArenaName);
+    }
+
     public interface ConnectionStateHandler {
-        void onConnectionState(Map<String, Tuple<String, Integer>> playerInfo);
+        void onConnectionState(List<ConnectionStateMessage.PlayerState> playerInfo);
     }
 
 }
\ No newline at end of file
@@ -51,6 +51,26 @@ public class CharReader {
         }
     }
 
+    public boolean hasRemaining() {
+        return this.currentPosition < this.message.length;
+    }
+
+    public double readDecimal() {
+        int start = this.currentPosition;
+        while (this.currentPosition < this.message.length && this.message[this.currentPosition] != ',') {
+            this.currentPosition++;
+        }
+        String text = new String(this.message, start, this.currentPosition - start);
+        if (this.currentPosition < this.message.length) {
+            this.currentPosition++;
+        }
+        try {
+            return text.isEmpty() ? 0 : Double.parseDouble(text);
+        } catch (NumberFormatException e) {
+            throw new RuntimeException("Invalid decimal format: " + text);
+        }
+    }
+
     public String readString() {
         int length = readNumber();
         String result = new String(this.message, currentPosition, length);
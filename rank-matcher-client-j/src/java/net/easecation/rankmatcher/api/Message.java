@@ -2,6 +2,7 @@ package net.easecation.rankmatcher.api;
 
 import net.easecation.rankmatcher.api.message.*;
 
+import java.math.BigDecimal;
 import java.nio.charset.StandardCharsets;
 import java.util.HashMap;
 import java.util.Map;
@@ -27,6 +28,11 @@ public interface Message {
         put(MessageType.MATCH_SUCCESS.getTypeId(), MatchSuccessMessage::new);
         put(MessageType.MATCH_FAILURE.getTypeId(), MatchFailureMessage::new);
         put(MessageType.FORMAT_ERROR.getTypeId(), FormatErrorMessage::new);
+        put(MessageType.DEQUEUED.getTypeId(), DequeuedMessage::new);
+        put(MessageType.BACKFILL_SUCCESS.getTypeId(), BackfillSuccessMessage::new);
+        put(MessageType.PENDING_MATCH.getTypeId(), PendingMatchMessage::new);
+        put(MessageType.MATCH_CANCELLED.getTypeId(), MatchCancelledMessage::new);
+        put(MessageType.UPDATE_PLAYER_FAILURE.getTypeId(), UpdatePlayerFailureMessage::new);
     }};
 
     MessageType getMessageType();
@@ -39,4 +45,11 @@ public interface Message {
         return string.getBytes(StandardCharsets.UTF_8).length + "," + string;
     }
 
+    /*
+     * 分数等可以是负数或小数的字段。不使用科学计数法，整数不带小数点
+     * */
+    static String writeDecimal(double value) {
+        return BigDecimal.valueOf(value).stripTrailingZeros().toPlainString();
+    }
+
 }
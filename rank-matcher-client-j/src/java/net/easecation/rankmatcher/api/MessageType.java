@@ -10,7 +10,16 @@ public enum MessageType {
     CONNECTION_STATE(6),
     MATCH_SUCCESS(7),
     MATCH_FAILURE(8),
-    FORMAT_ERROR(9);
+    FORMAT_ERROR(9),
+    MATCH_RESULT(10),
+    DEQUEUED(11),
+    BACKFILL(12),
+    BACKFILL_SUCCESS(13),
+    PENDING_MATCH(14),
+    ACCEPT_MATCH(15),
+    MATCH_CANCELLED(16),
+    UPDATE_PLAYER(17),
+    UPDATE_PLAYER_FAILURE(18);
 
     private final int id;
 
@@ -0,0 +1,30 @@
+package net.easecation.rankmatcher.api.message;
+
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+public class AcceptMatchMessage implements Message {
+
+    private int pendingId;
+    private String player;
+    private boolean accepted;
+
+    public static AcceptMatchMessage of(int pendingId, String player, boolean accepted) {
+        AcceptMatchMessage message = new AcceptMatchMessage();
+        message.pendingId = pendingId;
+        message.player = player;
+        message.accepted = accepted;
+        return message;
+    }
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.ACCEPT_MATCH;
+    }
+
+    @Override
+    public String toString() {
+        return PROTOCOL_VERSION + "," + getMessageType().getTypeId() + "," + pendingId + "," + Message.writeString(player) + "," + (accepted ? 1 : 0);
+    }
+
+}
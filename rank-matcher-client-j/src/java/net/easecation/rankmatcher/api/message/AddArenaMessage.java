@@ -1,19 +1,72 @@
 package net.easecation.rankmatcher.api.message;
 
+import lombok.AllArgsConstructor;
 import lombok.Data;
+import net.easecation.eccommons.adt.Tuple;
 import net.easecation.rankmatcher.api.Message;
 import net.easecation.rankmatcher.api.MessageType;
 
+import java.util.ArrayList;
+import java.util.List;
+
 @Data
 public class AddArenaMessage implements Message {
 
     private String arena;
-    private int numPlayers;
+    private int numPlayers;  // 每局的玩家数。设置了minPlayers时为每局最多的玩家数
+    private int numTeams = 1;  // 一局分成几个队伍，每个队伍人数相同
+    private boolean mutual = false;  // 互相接受模式：每位玩家的区间都必须包含同一局其他玩家的分数
+    private int expansion = EXPANSION_LINEAR;  // 区间扩散方式
+    private int expansionRatio = 1000;  // 指数扩散的倍率，千分比，例如1100表示每秒乘以1.1
+    private List<Tuple<Integer, Double>> expansionSteps = new ArrayList<>();  // (等待秒数, 区间半径)，半径为0表示不再限制分数
+    private double maxRankDiff = 0;  // 区间半径的上限，0表示不限制
+    private int minPlayers = 0;  // 每局最少的玩家数，0表示和numPlayers相同
+    private int fillAfterSecs = 0;  // 有玩家等待超过这么多秒以后，达到minPlayers就开始，否则等到numPlayers
+    private int priority = PRIORITY_LONGEST_WAIT;  // 可选的小队比一局需要的多时优先选择哪些
+    private String strategy = STRATEGY_RANK;  // 匹配策略
+    private List<Tuple<String, Integer>> roles = new ArrayList<>();  // 每个队伍的角色位置，(角色名称, 位置数量)。为空表示不区分角色，否则numPlayers必须等于numTeams × 位置总数
+    private int latencyThreshold = 0;  // 进入匹配池时的地区延迟阈值，毫秒。0表示不区分地区
+    private int latencySpeed = 0;  // 地区延迟阈值每秒放宽多少毫秒
+    private int maxLatencyThreshold = 0;  // 地区延迟阈值的上限，0表示不限制
+    private int recentCooldownSecs = 0;  // 一起匹配过的玩家在这么多秒内不会再分到同一局，0表示不限制
+    private int ratingSystem = RATING_NONE;  // 评分系统，设置以后AddPlayerMessage的rank为0时使用保存的评分
+    private int ratingParam = 0;  // Elo每局评分变化的最大值，默认32；Glicko-2时为tau的千分比，默认500
+    private double drawBeta = 0;  // 预测平局概率时表现的随机波动，单位和分数相同。0表示不按平局概率判断
+    private int minDrawQuality = 0;  // 一局的平局概率至少是多少，千分比
+    private int drawRelaxSecs = 0;  // 等待最久的小队等待了这么多秒以后，平局概率的要求降低到0。0表示不降低
+    private int acceptTimeoutSecs = 0;  // 匹配成功以后等待所有小队确认的秒数，0表示不需要确认，直接创建房间
+    private int botFillAfterSecs = 0;  // 等待最久的小队等待了这么多秒以后，真人凑不满一局时用机器人补满。0表示不使用机器人，区分角色时不能使用
+    private int botMinPlayers = 1;  // 使用机器人时一局至少要有几位真人
+    private List<AttributeRule> attributes = new ArrayList<>();  // 一局的所有小队必须取得一致的属性，例如地图、模式、语言
+
+    public static final int EXPANSION_LINEAR = 0;  // 每秒增加speed
+    public static final int EXPANSION_EXPONENTIAL = 1;  // 每秒乘以expansionRatio，并且至少和线性扩散一样快
+    public static final int EXPANSION_STEPS = 2;  // 按expansionSteps查表
+
+    public static final int PRIORITY_LONGEST_WAIT = 0;  // 等待时间长的优先
+    public static final int PRIORITY_SMALLEST_SPREAD = 1;  // 等待最久的小队优先，其余按分数和它的差距从小到大
+    public static final int PRIORITY_DRAW_PROBABILITY = 2;  // 等待最久的小队优先，其余按和它打成平局的概率从大到小，需要设置drawBeta
+
+    public static final int RATING_NONE = 0;  // 分数完全由大厅服务器决定
+    public static final int RATING_ELO = 1;
+    public static final int RATING_GLICKO2 = 2;
+
+    public static final int ATTRIBUTE_EXACT = 0;  // 只看小队的第一个取值，一局的所有小队必须相同
+    public static final int ATTRIBUTE_OVERLAP = 1;  // 一局的所有小队至少有一个共同的取值
+    public static final int ATTRIBUTE_SOFT = 2;  // 和ATTRIBUTE_OVERLAP相同，但小队等待超过relaxSecs以后不再要求
+
+    public static final String STRATEGY_RANK = "rank";  // 按分数排位
+    public static final String STRATEGY_FIFO = "fifo";  // 不看分数，按进入匹配池的先后顺序
 
     public static AddArenaMessage of(String arena, int numPlayers) {
+        return of(arena, numPlayers, 1);
+    }
+
+    public static AddArenaMessage of(String arena, int numPlayers, int numTeams) {
         AddArenaMessage message = new AddArenaMessage();
         message.arena = arena;
         message.numPlayers = numPlayers;
+        message.numTeams = numTeams;
         return message;
     }
 
@@ -24,6 +77,54 @@ public class AddArenaMessage implements Message {
 
     @Override
     public String toString() {
-        return PROTOCOL_VERSION + "," + getMessageType().getTypeId() + "," + Message.writeString(arena) + "," + numPlayers;
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(numPlayers);
+        sb.append(",").append(numTeams);
+        sb.append(",").append(mutual ? 1 : 0);
+        sb.append(",").append(expansion);
+        sb.append(",").append(expansionRatio);
+        sb.append(",").append(expansionSteps.size());
+        for (Tuple<Integer, Double> step : expansionSteps) {
+            sb.append(",").append(step.getFirst());
+            sb.append(",").append(Message.writeDecimal(step.getSecond()));
+        }
+        sb.append(",").append(Message.writeDecimal(maxRankDiff));
+        sb.append(",").append(minPlayers);
+        sb.append(",").append(fillAfterSecs);
+        sb.append(",").append(priority);
+        sb.append(",").append(Message.writeString(strategy));
+        sb.append(",").append(roles.size());
+        for (Tuple<String, Integer> role : roles) {
+            sb.append(",").append(Message.writeString(role.getFirst()));
+            sb.append(",").append(role.getSecond());
+        }
+        sb.append(",").append(latencyThreshold);
+        sb.append(",").append(latencySpeed);
+        sb.append(",").append(maxLatencyThreshold);
+        sb.append(",").append(recentCooldownSecs);
+        sb.append(",").append(ratingSystem);
+        sb.append(",").append(ratingParam);
+        sb.append(",").append(Message.writeDecimal(drawBeta));
+        sb.append(",").append(minDrawQuality);
+        sb.append(",").append(drawRelaxSecs);
+        sb.append(",").append(acceptTimeoutSecs);
+        sb.append(",").append(botFillAfterSecs);
+        sb.append(",").append(botMinPlayers);
+        sb.append(",").append(attributes.size());
+        for (AttributeRule attribute : attributes) {
+            sb.append(",").append(Message.writeString(attribute.getKey()));
+            sb.append(",").append(attribute.getKind());
+            sb.append(",").append(attribute.getRelaxSecs());
+        }
+        return sb.toString();
+    }
+
+    @Data
+    @AllArgsConstructor
+    public static class AttributeRule {
+        private String key;  // 属性名称
+        private int kind;  // ATTRIBUTE_EXACT、ATTRIBUTE_OVERLAP或ATTRIBUTE_SOFT
+        private int relaxSecs;  // 软性要求的放宽秒数，必须大于0。其他要求时忽略
     }
 }
@@ -1,20 +1,31 @@
 package net.easecation.rankmatcher.api.message;
 
 import lombok.Data;
+import net.easecation.eccommons.adt.Tuple;
 import net.easecation.rankmatcher.api.Message;
 import net.easecation.rankmatcher.api.MessageType;
 
+import java.util.ArrayList;
+import java.util.LinkedHashMap;
+import java.util.List;
+import java.util.Map;
+
 @Data
 public class AddPlayerMessage implements Message {
 
     private String arena;
     private String player;
-    private int rank;
+    private double rank;  // 可以是负数或小数
     private int length;  // 通常是1。用于按队伍为单位匹配时，以队长的名义和分数匹配，此时length为队伍成员的数量
-    private int initRankDiff;  // 初始化的分数扩散数值
-    private int speed;
+    private double initRankDiff;  // 初始化的分数扩散数值
+    private double speed;  // 区间每秒向两边各扩散多少分
+    private List<List<String>> roles = new ArrayList<>();  // 小队每位成员可以接受的角色，长度等于length。为空表示可以接受任何角色
+    private List<Tuple<String, Integer>> latencies = new ArrayList<>();  // (地区名称, 延迟毫秒数)，为空表示可以在任何地区开局
+    private List<String> avoid = new ArrayList<>();  // 不想分到同一局的玩家名称，例如屏蔽的玩家
+    private double rankDeviation = 0;  // 评分偏差，初始区间会加宽这么多。匹配池设置了评分系统并且rank为0时，0表示使用保存的评分偏差
+    private Map<String, List<String>> attributes = new LinkedHashMap<>();  // 属性名称 => 可以接受的取值，靠前的取值优先。没有的属性表示可以接受任何取值
 
-    public static AddPlayerMessage of(String arena, String player, int rank, int length, int initRankDiff, int speed) {
+    public static AddPlayerMessage of(String arena, String player, double rank, int length, double initRankDiff, double speed) {
         AddPlayerMessage message = new AddPlayerMessage();
         message.arena = arena;
         message.player = player;
@@ -32,7 +43,38 @@ public class AddPlayerMessage implements Message {
 
     @Override
     public String toString() {
-        return PROTOCOL_VERSION + "," + getMessageType().getTypeId() + "," + Message.writeString(arena) + "," + Message.writeString(player) + "," + rank + "," + length + "," + initRankDiff + "," + speed;
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena)).append(",").append(Message.writeString(player));
+        sb.append(",").append(Message.writeDecimal(rank));
+        sb.append(",").append(length);
+        sb.append(",").append(Message.writeDecimal(initRankDiff));
+        sb.append(",").append(Message.writeDecimal(speed));
+        sb.append(",").append(roles.size());
+        for (List<String> memberRoles : roles) {
+            sb.append(",").append(memberRoles.size());
+            for (String role : memberRoles) {
+                sb.append(",").append(Message.writeString(role));
+            }
+        }
+        sb.append(",").append(latencies.size());
+        for (Tuple<String, Integer> latency : latencies) {
+            sb.append(",").append(Message.writeString(latency.getFirst()));
+            sb.append(",").append(latency.getSecond());
+        }
+        sb.append(",").append(avoid.size());
+        for (String other : avoid) {
+            sb.append(",").append(Message.writeString(other));
+        }
+        sb.append(",").append(Message.writeDecimal(rankDeviation));
+        sb.append(",").append(attributes.size());
+        for (Map.Entry<String, List<String>> attribute : attributes.entrySet()) {
+            sb.append(",").append(Message.writeString(attribute.getKey()));
+            sb.append(",").append(attribute.getValue().size());
+            for (String value : attribute.getValue()) {
+                sb.append(",").append(Message.writeString(value));
+            }
+        }
+        return sb.toString();
     }
 
 }
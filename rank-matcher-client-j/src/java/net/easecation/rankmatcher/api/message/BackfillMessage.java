@@ -0,0 +1,60 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+import java.util.ArrayList;
+import java.util.LinkedHashMap;
+import java.util.List;
+import java.util.Map;
+
+@Data
+public class BackfillMessage implements Message {
+
+    private String arena;
+    private int stageRequestId;  // MatchSuccessMessage中的stageRequestId
+    private double rank;  // 房间的分数，通常是留下的玩家的平均分
+    private double rankDiff;  // 可以接受的分数区间为[rank - rankDiff, rank + rankDiff]，按匹配池的扩散方式扩大
+    private double speed;  // 区间每秒向两边各扩散多少分
+    private List<Integer> openSlots = new ArrayList<>();  // 每个队伍还缺几个人，下标是队伍编号。全为0或为空表示取消补位请求
+    private String region = "";  // 房间所在的地区，为空表示不限制
+    private Map<String, String> attributes = new LinkedHashMap<>();  // 房间的属性，通常是MatchSuccessMessage中的attributes。补位的小队必须能接受这些属性
+
+    public static BackfillMessage of(String arena, int stageRequestId, double rank, double rankDiff, double speed, List<Integer> openSlots) {
+        BackfillMessage message = new BackfillMessage();
+        message.arena = arena;
+        message.stageRequestId = stageRequestId;
+        message.rank = rank;
+        message.rankDiff = rankDiff;
+        message.speed = speed;
+        message.openSlots = openSlots;
+        return message;
+    }
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.BACKFILL;
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(stageRequestId);
+        sb.append(",").append(Message.writeDecimal(rank));
+        sb.append(",").append(Message.writeDecimal(rankDiff));
+        sb.append(",").append(Message.writeDecimal(speed));
+        sb.append(",").append(openSlots.size());
+        for (int slots : openSlots) {
+            sb.append(",").append(slots);
+        }
+        sb.append(",").append(Message.writeString(region));
+        sb.append(",").append(attributes.size());
+        for (Map.Entry<String, String> attribute : attributes.entrySet()) {
+            sb.append(",").append(Message.writeString(attribute.getKey()));
+            sb.append(",").append(Message.writeString(attribute.getValue()));
+        }
+        return sb.toString();
+    }
+}
@@ -0,0 +1,39 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.CharReader;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+import java.util.ArrayList;
+import java.util.List;
+
+@Data
+public class BackfillSuccessMessage implements Message {
+
+    private String arena;
+    private int stageRequestId;  // 补位的房间
+    private List<MatchSuccessMessage.MatchedParty> players = new ArrayList<>();  // team是小队补到的队伍编号
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.BACKFILL_SUCCESS;
+    }
+
+    @Override
+    public void decode(CharReader reader) {
+        arena = reader.readString();
+        stageRequestId = reader.readNumber();
+        players = MatchSuccessMessage.readParties(reader);
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(stageRequestId);
+        MatchSuccessMessage.writeParties(sb, players);
+        return sb.toString();
+    }
+
+}
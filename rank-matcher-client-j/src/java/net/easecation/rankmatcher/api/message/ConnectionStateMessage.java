@@ -1,25 +1,26 @@
 package net.easecation.rankmatcher.api.message;
 
+import lombok.AllArgsConstructor;
 import lombok.Data;
-import net.easecation.eccommons.adt.Tuple;
 import net.easecation.rankmatcher.api.CharReader;
 import net.easecation.rankmatcher.api.Message;
 import net.easecation.rankmatcher.api.MessageType;
 
-import java.util.LinkedHashMap;
-import java.util.Map;
+import java.util.ArrayList;
+import java.util.List;
 
 @Data
 public class ConnectionStateMessage implements Message {
 
-    private Map<String, Tuple<String, Integer>> playerInfo = new LinkedHashMap<>();
+    private List<PlayerState> playerInfo = new ArrayList<>();  // 同时在多个匹配池排队的玩家在每个匹配池各有一项
+    private List<ArenaStats> arenaStats = new ArrayList<>();  // 每个匹配池累计的对局质量
 
     @Override
     public MessageType getMessageType() {
         return MessageType.CONNECTION_STATE;
     }
 
-    public static ConnectionStateMessage of(Map<String, Tuple<String, Integer>> playerInfo) {
+    public static ConnectionStateMessage of(List<PlayerState> playerInfo) {
         ConnectionStateMessage message = new ConnectionStateMessage();
         message.playerInfo = playerInfo;
         return message;
@@ -30,22 +31,63 @@ public class ConnectionStateMessage implements Message {
         /*
         fn read_v1_connection_state(&mut self) -> Result<Packet, PacketFormat> {
             let number = self.read_number();
-            let player_info = DashMap::with_capacity(number as usize);
+            let mut player_info = Vec::with_capacity(number as usize);
             for _ in 0..number {
-                let player = self.read_string();
-                let arena = self.read_string();
-                let num_matched = self.read_number();
-                player_info.insert(player, (arena, num_matched));
+                player_info.push(PlayerState {
+                    player: self.read_string(),
+                    arena: self.read_string(),
+                    num_matched: self.read_number(),
+                    wait_secs: 0,
+                    eta_secs: 0,
+                    position: 0,
+                });
             }
-            Ok(Packet::ConnectionState { player_info })
+            // 旧版本的服务器没有后面的字段，读到的是0
+            let number = self.read_number();
+            for state in player_info.iter_mut().take(number as usize) {
+                state.wait_secs = self.read_number();
+                state.eta_secs = self.read_number();
+                state.position = self.read_number();
+            }
+            let number = self.read_number();
+            let mut arena_stats = Vec::with_capacity(number as usize);
+            for _ in 0..number {
+                arena_stats.push(ArenaStats {
+                    arena: self.read_string(),
+                    matches: self.read_number(),
+                    quality: self.read_quality(),
+                });
+            }
+            Ok(Packet::ConnectionState {
+                player_info,
+                arena_stats,
+            })
         }
         */
+        // 列表的格式和最初的版本相同，后来加的字段按相同的顺序写在列表后面
         int number = reader.readNumber();
         for (int i = 0; i < number; i++) {
             String player = reader.readString();
             String arena = reader.readString();
             int numMatched = reader.readNumber();
-            playerInfo.put(player, Tuple.of(arena, numMatched));
+            playerInfo.add(new PlayerState(player, arena, numMatched, 0, 0, 0));
+        }
+        if (reader.hasRemaining()) {
+            number = reader.readNumber();
+            for (int i = 0; i < number && i < playerInfo.size(); i++) {
+                playerInfo.get(i).setWaitSeconds(reader.readNumber());
+                playerInfo.get(i).setEtaSeconds(reader.readNumber());
+                playerInfo.get(i).setPosition(reader.readNumber());
+            }
+        }
+        if (reader.hasRemaining()) {
+            number = reader.readNumber();
+            for (int i = 0; i < number; i++) {
+                String arena = reader.readString();
+                int matches = reader.readNumber();
+                MatchSuccessMessage.MatchQuality quality = new MatchSuccessMessage.MatchQuality(reader.readNumber(), reader.readNumber(), reader.readNumber(), reader.readNumber(), reader.readNumber());
+                arenaStats.add(new ArenaStats(arena, matches, quality));
+            }
         }
     }
 
@@ -53,12 +95,47 @@ public class ConnectionStateMessage implements Message {
     public String toString() {
         StringBuilder sb = new StringBuilder();
         sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(playerInfo.size());
-        for (Map.Entry<String, Tuple<String, Integer>> entry : playerInfo.entrySet()) {
-            sb.append(",").append(Message.writeString(entry.getKey()));
-            sb.append(",").append(Message.writeString(entry.getValue().getFirst()));
-            sb.append(",").append(entry.getValue().getSecond());
+        for (PlayerState state : playerInfo) {
+            sb.append(",").append(Message.writeString(state.getPlayer()));
+            sb.append(",").append(Message.writeString(state.getArena()));
+            sb.append(",").append(state.getNumMatched());
+        }
+        sb.append(",").append(playerInfo.size());
+        for (PlayerState state : playerInfo) {
+            sb.append(",").append(state.getWaitSeconds());
+            sb.append(",").append(state.getEtaSeconds());
+            sb.append(",").append(state.getPosition());
+        }
+        sb.append(",").append(arenaStats.size());
+        for (ArenaStats stats : arenaStats) {
+            sb.append(",").append(Message.writeString(stats.getArena()));
+            sb.append(",").append(stats.getMatches());
+            sb.append(",").append(stats.getQuality().getRankSpread());
+            sb.append(",").append(stats.getQuality().getRankStdDev());
+            sb.append(",").append(stats.getQuality().getTeamImbalance());
+            sb.append(",").append(stats.getQuality().getMaxWaitSeconds());
+            sb.append(",").append(stats.getQuality().getAvgWaitSeconds());
         }
         return sb.toString();
     }
 
+    @Data
+    @AllArgsConstructor
+    public static class PlayerState {
+        private String player;  // 玩家名称，小队是队长的名字
+        private String arena;  // 匹配池名称
+        private int numMatched;  // 区间内同时匹配的最多人数
+        private int waitSeconds;  // 已经等待的秒数
+        private int etaSeconds;  // 估计还要等待的秒数，0表示匹配池的记录还不够，无法估计
+        private int position;  // 按进入匹配池的先后排在第几位，从1开始
+    }
+
+    @Data
+    @AllArgsConstructor
+    public static class ArenaStats {
+        private String arena;  // 匹配池名称
+        private int matches;  // 匹配成功的局数
+        private MatchSuccessMessage.MatchQuality quality;  // 各项指标在所有对局中的平均值，maxWaitSeconds是所有对局中等待最久的秒数
+    }
+
 }
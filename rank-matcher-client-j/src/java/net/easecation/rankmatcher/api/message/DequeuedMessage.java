@@ -0,0 +1,36 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.CharReader;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+@Data
+public class DequeuedMessage implements Message {
+
+    private String arena;  // 玩家被移出的匹配池
+    private String player;
+    private String matchedArena;  // 玩家匹配成功的匹配池
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.DEQUEUED;
+    }
+
+    @Override
+    public void decode(CharReader reader) {
+        arena = reader.readString();
+        player = reader.readString();
+        matchedArena = reader.readString();
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(Message.writeString(player));
+        sb.append(",").append(Message.writeString(matchedArena));
+        return sb.toString();
+    }
+
+}
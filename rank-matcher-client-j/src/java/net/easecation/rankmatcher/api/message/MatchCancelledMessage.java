@@ -0,0 +1,54 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.CharReader;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+import java.util.ArrayList;
+import java.util.List;
+
+@Data
+public class MatchCancelledMessage implements Message {
+
+    private String arena;
+    private int pendingId;
+    private List<String> requeued = new ArrayList<>();  // 已经接受的小队，回到匹配池，区间和等待时间保持不变
+    private List<String> removed = new ArrayList<>();  // 拒绝或者没有回复的小队，已经移出匹配池
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.MATCH_CANCELLED;
+    }
+
+    @Override
+    public void decode(CharReader reader) {
+        arena = reader.readString();
+        pendingId = reader.readNumber();
+        int number = reader.readNumber();
+        for (int i = 0; i < number; i++) {
+            requeued.add(reader.readString());
+        }
+        number = reader.readNumber();
+        for (int i = 0; i < number; i++) {
+            removed.add(reader.readString());
+        }
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(pendingId);
+        sb.append(",").append(requeued.size());
+        for (String player : requeued) {
+            sb.append(",").append(Message.writeString(player));
+        }
+        sb.append(",").append(removed.size());
+        for (String player : removed) {
+            sb.append(",").append(Message.writeString(player));
+        }
+        return sb.toString();
+    }
+
+}
@@ -0,0 +1,39 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+import java.util.ArrayList;
+import java.util.List;
+
+@Data
+public class MatchResultMessage implements Message {
+
+    private int stageRequestId;  // MatchSuccessMessage中的stageRequestId
+    private List<Integer> placements = new ArrayList<>();  // 每个队伍的名次，下标是队伍编号。0表示第一名，名次相同表示平局。不分队时是每个小队的名次，按匹配成功时的顺序
+
+    public static MatchResultMessage of(int stageRequestId, List<Integer> placements) {
+        MatchResultMessage message = new MatchResultMessage();
+        message.stageRequestId = stageRequestId;
+        message.placements = placements;
+        return message;
+    }
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.MATCH_RESULT;
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId());
+        sb.append(",").append(stageRequestId);
+        sb.append(",").append(placements.size());
+        for (int placement : placements) {
+            sb.append(",").append(placement);
+        }
+        return sb.toString();
+    }
+}
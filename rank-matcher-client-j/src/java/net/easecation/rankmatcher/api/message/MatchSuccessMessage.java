@@ -1,46 +1,70 @@
 package net.easecation.rankmatcher.api.message;
 
+import lombok.AllArgsConstructor;
 import lombok.Data;
-import net.easecation.eccommons.adt.Tuple;
 import net.easecation.rankmatcher.api.CharReader;
 import net.easecation.rankmatcher.api.Message;
 import net.easecation.rankmatcher.api.MessageType;
 
 import java.util.ArrayList;
+import java.util.LinkedHashMap;
 import java.util.List;
+import java.util.Map;
 
 @Data
 public class MatchSuccessMessage implements Message {
 
     private String arena;
     private int stageRequestId;
-    private List<Tuple<String, Integer>> players = new ArrayList<>();
+    private List<MatchedParty> players = new ArrayList<>();
+    private MatchQuality quality;
+    private List<MatchedBot> bots = new ArrayList<>();  // 补满这一局的机器人，需要游戏服务器创建AI
+    private Map<String, String> attributes = new LinkedHashMap<>();  // 这一局取得一致的属性，例如地图和模式
 
     @Override
     public void decode(CharReader reader) {
         /*
-        let arena = self.read_string();
-        let stage_request_id = self.read_number();
-        let number = self.read_number();
-        let mut players = Vec::with_capacity(number as usize);
-        for _ in 0..number {
-            let player = self.read_string();
-            let length = self.read_number();
-            players.push((player, length));
+        fn read_v1_match_success(&mut self) -> Result<Packet, PacketFormat> {
+            let arena = self.read_string();
+            let stage_request_id = self.read_number();
+            let players = self.read_matched_parties();
+            let quality = self.read_quality();
+            let number = self.read_number();
+            let mut bots = Vec::with_capacity(number as usize);
+            for _ in 0..number {
+                let team = self.read_number() as usize;
+                let rank = self.read_decimal()?;
+                bots.push(Bot { team, rank });
+            }
+            let number = self.read_number();
+            let attributes = (0..number)
+                .map(|_| (self.read_string(), self.read_string()))
+                .collect();
+            Ok(Packet::MatchSuccess {
+                arena,
+                stage_request_id,
+                players,
+                quality,
+                bots,
+                attributes,
+            })
         }
-        Ok(Packet::MatchSuccess {
-            arena,
-            stage_request_id,
-            players,
-        })
-         */
+        */
         arena = reader.readString();
         stageRequestId = reader.readNumber();
-        int number = reader.readNumber();
-        for (int i = 0; i < number; i++) {
-            String player = reader.readString();
-            int length = reader.readNumber();
-            players.add(Tuple.of(player, length));
+        players = readParties(reader);
+        quality = new MatchQuality(reader.readNumber(), reader.readNumber(), reader.readNumber(), reader.readNumber(), reader.readNumber());
+        if (reader.hasRemaining()) {
+            int number = reader.readNumber();
+            for (int i = 0; i < number; i++) {
+                bots.add(new MatchedBot(reader.readNumber(), reader.readDecimal()));
+            }
+        }
+        if (reader.hasRemaining()) {
+            int number = reader.readNumber();
+            for (int i = 0; i < number; i++) {
+                attributes.put(reader.readString(), reader.readString());
+            }
         }
     }
 
@@ -49,10 +73,21 @@ public class MatchSuccessMessage implements Message {
         StringBuilder sb = new StringBuilder();
         sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
         sb.append(",").append(stageRequestId);
-        sb.append(",").append(players.size());
-        for (Tuple<String, Integer> player : players) {
-            sb.append(",").append(Message.writeString(player.getFirst()));
-            sb.append(",").append(player.getSecond());
+        writeParties(sb, players);
+        sb.append(",").append(quality.getRankSpread());
+        sb.append(",").append(quality.getRankStdDev());
+        sb.append(",").append(quality.getTeamImbalance());
+        sb.append(",").append(quality.getMaxWaitSeconds());
+        sb.append(",").append(quality.getAvgWaitSeconds());
+        sb.append(",").append(bots.size());
+        for (MatchedBot bot : bots) {
+            sb.append(",").append(bot.getTeam());
+            sb.append(",").append(Message.writeDecimal(bot.getRank()));
+        }
+        sb.append(",").append(attributes.size());
+        for (Map.Entry<String, String> attribute : attributes.entrySet()) {
+            sb.append(",").append(Message.writeString(attribute.getKey()));
+            sb.append(",").append(Message.writeString(attribute.getValue()));
         }
         return sb.toString();
     }
@@ -62,4 +97,88 @@ public class MatchSuccessMessage implements Message {
         return MessageType.MATCH_SUCCESS;
     }
 
+    static List<MatchedParty> readParties(CharReader reader) {
+        /*
+        fn read_matched_parties(&mut self) -> Vec<MatchedParty> {
+            let number = self.read_number();
+            let mut players = Vec::with_capacity(number as usize);
+            for _ in 0..number {
+                players.push(MatchedParty {
+                    player: self.read_string(),
+                    length: self.read_number(),
+                    team: 0,
+                    roles: Vec::new(),
+                });
+            }
+            let number = self.read_number();
+            for party in players.iter_mut().take(number as usize) {
+                party.team = self.read_number();
+                let count = self.read_number();
+                party.roles = (0..count).map(|_| self.read_string()).collect();
+            }
+            players
+        }
+        */
+        int number = reader.readNumber();
+        List<MatchedParty> parties = new ArrayList<>(number);
+        for (int i = 0; i < number; i++) {
+            parties.add(new MatchedParty(reader.readString(), reader.readNumber(), 0, new ArrayList<>()));
+        }
+        // 队伍编号和角色写在列表后面，旧版本的服务器不会发送
+        if (reader.hasRemaining()) {
+            number = Math.min(reader.readNumber(), parties.size());
+            for (int i = 0; i < number; i++) {
+                MatchedParty party = parties.get(i);
+                party.setTeam(reader.readNumber());
+                int count = reader.readNumber();
+                for (int j = 0; j < count; j++) {
+                    party.getRoles().add(reader.readString());
+                }
+            }
+        }
+        return parties;
+    }
+
+    static void writeParties(StringBuilder sb, List<MatchedParty> parties) {
+        sb.append(",").append(parties.size());
+        for (MatchedParty party : parties) {
+            sb.append(",").append(Message.writeString(party.getPlayer()));
+            sb.append(",").append(party.getLength());
+        }
+        sb.append(",").append(parties.size());
+        for (MatchedParty party : parties) {
+            sb.append(",").append(party.getTeam());
+            sb.append(",").append(party.getRoles().size());
+            for (String role : party.getRoles()) {
+                sb.append(",").append(Message.writeString(role));
+            }
+        }
+    }
+
+    @Data
+    @AllArgsConstructor
+    public static class MatchedParty {
+        private String player;  // 玩家名称，小队则为队长名称
+        private int length;  // 小队人数，通常是1
+        private int team;  // 分到的队伍编号，从0开始
+        private List<String> roles;  // 每位成员分到的角色，顺序和AddPlayerMessage中的相同。匹配池不区分角色时为空
+    }
+
+    @Data
+    @AllArgsConstructor
+    public static class MatchedBot {
+        private int team;  // 分到的队伍编号，从0开始
+        private double rank;  // 机器人的分数，是这一局真人的平均分
+    }
+
+    @Data
+    @AllArgsConstructor
+    public static class MatchQuality {
+        private int rankSpread;  // 最高分和最低分的差
+        private int rankStdDev;  // 分数的标准差
+        private int teamImbalance;  // 各队伍总分最高和最低的差
+        private int maxWaitSeconds;  // 等待最久的玩家等待的秒数
+        private int avgWaitSeconds;  // 平均等待的秒数
+    }
+
 }
@@ -0,0 +1,42 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.CharReader;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+import java.util.ArrayList;
+import java.util.List;
+
+@Data
+public class PendingMatchMessage implements Message {
+
+    private String arena;
+    private int pendingId;  // 回复AcceptMatchMessage时使用
+    private List<MatchSuccessMessage.MatchedParty> players = new ArrayList<>();  // 这个大厅服务器添加的、需要确认的小队
+    private int timeoutSecs;  // 超过这么多秒还没有回复的小队当作拒绝
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.PENDING_MATCH;
+    }
+
+    @Override
+    public void decode(CharReader reader) {
+        arena = reader.readString();
+        pendingId = reader.readNumber();
+        players = MatchSuccessMessage.readParties(reader);
+        timeoutSecs = reader.readNumber();
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(pendingId);
+        MatchSuccessMessage.writeParties(sb, players);
+        sb.append(",").append(timeoutSecs);
+        return sb.toString();
+    }
+
+}
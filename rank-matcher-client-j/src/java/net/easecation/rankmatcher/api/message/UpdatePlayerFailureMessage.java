@@ -0,0 +1,36 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.CharReader;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+@Data
+public class UpdatePlayerFailureMessage implements Message {
+
+    private String arena;
+    private String player;
+    private String errorMessage;  // 例如玩家不在这个匹配池排队
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.UPDATE_PLAYER_FAILURE;
+    }
+
+    @Override
+    public void decode(CharReader reader) {
+        arena = reader.readString();
+        player = reader.readString();
+        errorMessage = reader.readString();
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena));
+        sb.append(",").append(Message.writeString(player));
+        sb.append(",").append(Message.writeString(errorMessage));
+        return sb.toString();
+    }
+
+}
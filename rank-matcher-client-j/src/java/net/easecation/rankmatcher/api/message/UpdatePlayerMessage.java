@@ -0,0 +1,52 @@
+package net.easecation.rankmatcher.api.message;
+
+import lombok.Data;
+import net.easecation.rankmatcher.api.Message;
+import net.easecation.rankmatcher.api.MessageType;
+
+import java.util.ArrayList;
+import java.util.List;
+
+@Data
+public class UpdatePlayerMessage implements Message {
+
+    private String arena;
+    private String player;
+    private double rank;  // 新的分数。匹配池设置了评分系统时，0表示保持原来的分数
+    private int length;  // 新的小队人数
+    private double speed;  // 新的扩散速度，只影响以后的扩散，已经扩大的区间保持不变
+    private List<List<String>> roles = new ArrayList<>();  // 小队每位成员可以接受的角色，和AddPlayerMessage中的相同
+
+    public static UpdatePlayerMessage of(String arena, String player, double rank, int length, double speed) {
+        UpdatePlayerMessage message = new UpdatePlayerMessage();
+        message.arena = arena;
+        message.player = player;
+        message.rank = rank;
+        message.length = length;
+        message.speed = speed;
+        return message;
+    }
+
+    @Override
+    public MessageType getMessageType() {
+        return MessageType.UPDATE_PLAYER;
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder sb = new StringBuilder();
+        sb.append(PROTOCOL_VERSION).append(",").append(getMessageType().getTypeId()).append(",").append(Message.writeString(arena)).append(",").append(Message.writeString(player));
+        sb.append(",").append(Message.writeDecimal(rank));
+        sb.append(",").append(length);
+        sb.append(",").append(Message.writeDecimal(speed));
+        sb.append(",").append(roles.size());
+        for (List<String> memberRoles : roles) {
+            sb.append(",").append(memberRoles.size());
+            for (String role : memberRoles) {
+                sb.append(",").append(Message.writeString(role));
+            }
+        }
+        return sb.toString();
+    }
+
+}
@@ -1,12 +1,326 @@
 // Rank matching algorithm
+use crate::attribute::AttributeRule;
+use crate::quality::{MatchQuality, QualityStats};
+use crate::rating::RatingSystem;
+use crate::role;
+use crate::wait::WaitHistory;
 use dashmap::DashMap;
-use std::collections::HashMap;
-use std::{borrow::Borrow, collections::HashSet, hash::Hash, sync::Arc};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// 匹配池的配置，注册匹配池时确定
+#[derive(Clone, Debug)]
+pub struct ArenaConfig {
+    // 一局的最少和最多玩家数
+    pub min_players: usize,
+    pub max_players: usize,
+    // 等待最久的玩家等待超过这个时间后，达到最少玩家数就可以开始，否则要等到最多玩家数
+    pub fill_after: Duration,
+    // 一局分为几个队伍，1表示不分队
+    pub num_teams: usize,
+    // 互相接受模式：一局中每位玩家的当前区间都必须包含其他所有玩家原本的分数
+    pub mutual: bool,
+    // 区间的扩散方式
+    pub expansion: Expansion,
+    // 区间半径的上限，None表示不限制
+    pub max_rank_diff: Option<f64>,
+    // 可选的小队比一局需要的多时，优先选择哪些小队
+    pub priority: Priority,
+    // 每个队伍的角色位置，(角色名称, 位置数量)。为空表示不区分角色，否则每局必须填满所有位置
+    pub roles: Vec<(String, usize)>,
+    // 地区延迟的阈值，None表示不区分地区
+    pub latency: Option<Latency>,
+    // 一起匹配过的玩家在这段时间内不会再分到同一局，0表示不限制
+    pub recent_cooldown: Duration,
+    // 评分系统，None表示分数完全由大厅服务器决定
+    pub rating: Option<RatingSystem>,
+    // 按预测的平局概率判断一局是否公平，None表示只看分数区间
+    pub draw: Option<DrawCriterion>,
+    // 匹配成功以后等待所有小队确认的时间，None表示不需要确认
+    pub accept_timeout: Option<Duration>,
+    // 真人凑不满一局时用机器人补满，None表示不使用机器人
+    pub bots: Option<BotFill>,
+    // 一局的所有小队必须取得一致的属性，为空表示不看属性
+    pub attributes: Vec<AttributeRule>,
+}
+
+// 机器人补位：等待最久的小队等待超过after，并且和它互相接受的真人至少有min_players位时，用机器人补满一局
+#[derive(Clone, Copy, Debug)]
+pub struct BotFill {
+    pub after: Duration,
+    // 一局至少要有几位真人
+    pub min_players: usize,
+}
+
+// 按TrueSkill的方法，根据分数和评分偏差预测一局打成平局的概率（对局质量），越接近1越公平
+#[derive(Clone, Copy, Debug)]
+pub struct DrawCriterion {
+    // 表现的随机波动，分数相差beta的两位玩家大约有76%的概率分出高下
+    pub beta: f64,
+    // 一局的平局概率至少是多少
+    pub min_quality: f64,
+    // 等待最久的小队等待了这么久以后，要求降低到0。为0表示不降低
+    pub relax: Duration,
+}
+
+impl DrawCriterion {
+    // 等待了waited以后，一局的平局概率至少是多少
+    pub fn min_quality_after(&self, waited: Duration) -> f64 {
+        if self.relax.is_zero() {
+            return self.min_quality;
+        }
+        let remaining = 1.0 - waited.as_secs_f64() / self.relax.as_secs_f64();
+        self.min_quality * f64::max(remaining, 0.0)
+    }
+}
+
+// 一局的所有小队必须在同一个地区的延迟都不超过各自的阈值。阈值和区间一样随等待的真实时间放宽
+#[derive(Clone, Copy, Debug)]
+pub struct Latency {
+    // 进入匹配池时的阈值，毫秒
+    pub init: u64,
+    // 阈值每秒放宽多少毫秒
+    pub speed: u64,
+    // 阈值的上限，None表示不限制
+    pub max: Option<u64>,
+}
+
+impl Latency {
+    // 等待了waited以后的阈值
+    pub fn threshold_after(&self, waited: Duration) -> u64 {
+        let threshold = self.init as f64 + self.speed as f64 * waited.as_secs_f64();
+        // 浮点数转换成u64时超出范围会取u64::MAX
+        match self.max {
+            Some(max) => u64::min(threshold as u64, max),
+            None => threshold as u64,
+        }
+    }
+}
+
+// 可选的小队比一局需要的多时，优先选择哪些小队
+#[derive(Clone, Copy, Debug)]
+pub enum Priority {
+    // 等待时间长的优先
+    LongestWait,
+    // 等待最久的小队优先，其余小队按分数和它的差距从小到大，这样一局的分数跨度小
+    SmallestSpread,
+    // 等待最久的小队优先，其余小队按和它打成平局的概率从大到小。需要设置平局概率的标准
+    DrawProbability,
+}
+
+impl ArenaConfig {
+    // 把区间半径限制在上限以内
+    pub fn cap_rank_diff(&self, rank_diff: f64) -> f64 {
+        match self.max_rank_diff {
+            Some(max_rank_diff) => f64::min(rank_diff, max_rank_diff),
+            None => rank_diff,
+        }
+    }
+
+    // 每个队伍中每个角色的位置数量，下标是角色编号
+    pub fn role_slots(&self) -> Vec<usize> {
+        self.roles.iter().map(|&(_, count)| count).collect()
+    }
+
+    // 检查配置是否合理，返回不合理的原因
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let role_slots = self.role_slots().iter().sum::<usize>();
+        let duplicate_role =
+            self.roles.iter().enumerate().any(|(i, (role, _count))| {
+                self.roles[..i].iter().any(|(other, _count)| other == role)
+            });
+        let duplicate_attribute = self.attributes.iter().enumerate().any(|(i, rule)| {
+            self.attributes[..i]
+                .iter()
+                .any(|other| other.key == rule.key)
+        });
+        if self.max_players == 0 {
+            Err("匹配池的每局玩家数为0")
+        } else if self.min_players > self.max_players {
+            Err("每局最少玩家数大于最多玩家数")
+        } else if self.min_players.div_ceil(self.num_teams) * self.num_teams > self.max_players {
+            Err("每局的玩家数都不能平均分到各个队伍")
+        } else if matches!(self.priority, Priority::DrawProbability) && self.draw.is_none() {
+            Err("按平局概率优先选择时必须设置表现波动")
+        } else if self.draw.is_some_and(|draw| draw.min_quality > 1.0) {
+            Err("平局概率的要求超过了1000‰")
+        } else if self.roles.len() > role::MAX_ROLES {
+            Err("角色数量超过了上限")
+        } else if duplicate_role {
+            Err("有角色重复出现")
+        } else if !self.roles.is_empty()
+            && (role_slots * self.num_teams != self.max_players
+                || self.min_players != self.max_players)
+        {
+            Err("区分角色时每局的人数必须固定为队伍数 × 每队的角色位置数")
+        } else if duplicate_attribute {
+            Err("有属性重复出现")
+        } else if self.bots.is_some() && !self.roles.is_empty() {
+            Err("区分角色的匹配池不能使用机器人补位")
+        } else if self
+            .bots
+            .is_some_and(|bots| bots.min_players > self.max_players)
+        {
+            Err("使用机器人时最少的真人数大于每局玩家数")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// 区间的扩散方式。区间半径只由进入匹配池以后经过的真实时间决定，和定时器的频率无关
+#[derive(Clone, Debug)]
+pub enum Expansion {
+    // 区间半径每秒增加speed
+    Linear,
+    // 区间半径每秒乘以ratio（千分比，例如1100表示1.1倍），并且至少和线性扩散一样快
+    Exponential { ratio: usize },
+    // 按等待时间查表，(等待秒数, 区间半径)，必须按秒数从小到大排列。
+    // 等待超过这个秒数后区间半径至少是这个值，None表示不再限制分数
+    Steps(Vec<(u64, Option<f64>)>),
+}
+
+impl Expansion {
+    // 到now时的区间半径。按速度和倍率扩散时从expanding_since开始计算，查表时按进入匹配池以后等待的时间
+    fn rank_diff_at(&self, player: &Player, now: Instant) -> f64 {
+        let init = player.initial_rank_diff();
+        let expanded = now.saturating_duration_since(player.expanding_since);
+        let linear = init + player.speed * expanded.as_secs_f64();
+        match self {
+            Expansion::Linear => linear,
+            Expansion::Exponential { ratio } => {
+                let factor = (*ratio as f64 / 1000.0).powf(expanded.as_secs_f64());
+                f64::max(init * factor, linear)
+            }
+            Expansion::Steps(steps) => {
+                let waited = now.saturating_duration_since(player.enqueued_at).as_secs();
+                match steps.iter().rev().find(|(secs, _diff)| *secs <= waited) {
+                    Some((_secs, Some(diff))) => f64::max(init, *diff),
+                    Some((_secs, None)) => f64::INFINITY,
+                    None => init,
+                }
+            }
+        }
+    }
+}
+
+// 匹配池中的一个玩家（或者一个小队，以队长的名义匹配）
+#[derive(Clone, Debug)]
+pub struct Player {
+    // 玩家原本的分数，可以是负数或小数
+    pub rank: f64,
+    // 进入匹配池时的区间半径，不包括评分偏差
+    pub init_rank_diff: f64,
+    // 评分偏差，表示分数有多不确定。偏差大的玩家初始区间更宽
+    pub deviation: f64,
+    // 当前的区间半径，可以接受的分数区间为[rank - rank_diff, rank + rank_diff]。无穷大表示不再限制分数
+    pub rank_diff: f64,
+    // 小队的人数，通常是1
+    pub length: usize,
+    // 区间扩散的速度，每秒扩散多少分
+    pub speed: f64,
+    // 进入匹配池的时间
+    pub enqueued_at: Instant,
+    // 区间从这个时间开始从initial_rank_diff扩散，通常和enqueued_at相同。修改玩家时从当前的区间重新开始扩散
+    pub expanding_since: Instant,
+    // 每位成员可以接受的角色，第i位为1表示可以接受第i个角色。为空表示所有成员都可以接受任何角色
+    pub roles: Vec<u64>,
+    // (地区名称, 延迟毫秒数)。为空表示可以在任何地区开局
+    pub latencies: Vec<(String, u64)>,
+    // (属性名称, 可以接受的取值)，靠前的取值优先。没有发送的属性表示可以接受任何取值
+    pub attributes: Vec<(String, Vec<String>)>,
+}
+
+impl Player {
+    // 进入匹配池时实际使用的区间半径
+    pub fn initial_rank_diff(&self) -> f64 {
+        self.init_rank_diff + self.deviation
+    }
+
+    // 以当前的区间为起点重新开始扩散，之后修改分数或扩散速度不会丢掉已经扩大的区间
+    pub fn rebase_expansion(&mut self, now: Instant) {
+        self.init_rank_diff = self.rank_diff - self.deviation;
+        self.expanding_since = now;
+    }
+
+    pub fn rank_min(&self) -> f64 {
+        self.rank - self.rank_diff
+    }
+
+    pub fn rank_max(&self) -> f64 {
+        self.rank + self.rank_diff
+    }
+
+    // 每位成员可以接受的角色，长度等于小队人数
+    pub fn member_roles(&self) -> Vec<u64> {
+        if self.roles.is_empty() {
+            vec![role::ANY_ROLE; self.length]
+        } else {
+            self.roles.clone()
+        }
+    }
+
+    // 延迟阈值为threshold时，能否在region开局
+    pub fn accepts_region(&self, region: &str, threshold: u64) -> bool {
+        self.latencies.is_empty()
+            || self
+                .latencies
+                .iter()
+                .any(|(name, latency)| name == region && *latency <= threshold)
+    }
+}
+
+// 已经开始的房间请求的补位
+#[derive(Clone, Debug)]
+pub struct Backfill {
+    // 房间的分数和区间，区间和玩家的一样随等待时间扩大。人数为0
+    pub anchor: Player,
+    // 每个队伍还缺几个人，下标是队伍编号
+    pub open_slots: Vec<usize>,
+    // 房间所在的地区，None表示不限制
+    pub region: Option<String>,
+    // 房间的属性，(属性名称, 取值)
+    pub attributes: Vec<(String, String)>,
+    // 请求补位的大厅服务器
+    pub sender: SocketAddr,
+}
+
+impl Backfill {
+    // 小队和房间互相接受：小队的分数在房间的区间内，房间的分数也在小队的区间内
+    pub fn accepts(&self, player: &Player) -> bool {
+        self.anchor.rank_min() <= player.rank
+            && player.rank <= self.anchor.rank_max()
+            && player.rank_min() <= self.anchor.rank
+            && self.anchor.rank <= player.rank_max()
+    }
+}
+
+// 每个排队的小队的(玩家, 区间内同时匹配的最多人数, 已经等待的秒数, 估计还要等待的秒数, 排队的位置)，
+// 估计还要等待的秒数为0表示记录不够，无法估计。排队的位置按进入匹配池的先后计算，从1开始
+pub type PlayerStates<T> = Arc<Vec<(T, u64, u64, u64, u64)>>;
 
 // 一个匹配池
 #[derive(Clone)]
 pub struct Arena<T> {
-    players: Arc<DashMap<T, (usize, usize, usize, usize)>>,
+    players: Arc<DashMap<T, Player>>,
+    // 每位玩家不想分到同一局的玩家，和玩家一起加入和删除
+    avoid: Arc<DashMap<T, Vec<T>>>,
+    // 每位玩家最近一起匹配过的玩家，以及匹配的时间。玩家离开匹配池以后也会保留到冷却时间结束
+    recent: Arc<DashMap<T, Vec<(T, Instant)>>>,
+    // 累计的对局质量
+    stats: Arc<Mutex<QualityStats>>,
+    // 等待补位的房间，按stage_request_id索引
+    backfills: Arc<DashMap<u64, Backfill>>,
+    // 最近匹配成功的小队等待的时间，用来估计排队中的小队还要等多久
+    waits: Arc<Mutex<WaitHistory>>,
+    // 排位定时器每秒计算一次的小队状态，所有订阅状态的大厅服务器共用
+    states: Arc<Mutex<PlayerStates<T>>>,
 }
 
 impl<T> Arena<T>
@@ -16,33 +330,128 @@ where
     pub fn new() -> Self {
         Arena {
             players: Arc::new(DashMap::new()),
+            avoid: Arc::new(DashMap::new()),
+            recent: Arc::new(DashMap::new()),
+            stats: Arc::new(Mutex::new(QualityStats::default())),
+            backfills: Arc::new(DashMap::new()),
+            waits: Arc::new(Mutex::new(WaitHistory::default())),
+            states: Arc::new(Mutex::new(Arc::new(Vec::new()))),
+        }
+    }
+}
+
+// 不能分到同一局的玩家，两个方向都会记录
+pub struct Conflicts<T> {
+    pairs: HashMap<T, HashSet<T>>,
+}
+
+impl<T> Conflicts<T>
+where
+    T: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Conflicts {
+            pairs: HashMap::new(),
         }
     }
+
+    pub fn add(&mut self, a: &T, b: &T) {
+        self.pairs.entry(a.clone()).or_default().insert(b.clone());
+        self.pairs.entry(b.clone()).or_default().insert(a.clone());
+    }
+
+    pub fn conflicts(&self, a: &T, b: &T) -> bool {
+        self.pairs.get(a).is_some_and(|set| set.contains(b))
+    }
 }
 
 impl<T> Arena<T>
 where
     T: Hash + Eq,
 {
-    pub fn insert(
-        &self,
-        id: T,
-        length: usize,
-        rank_min: usize,
-        rank_max: usize,
-        speed: usize,
-    ) -> Option<(usize, usize, usize, usize)> {
-        self.players.insert(id, (rank_min, rank_max, length, speed))
-    }
-
-    pub fn remove<Q>(&self, id: &Q) -> Option<(usize, usize, usize, usize)>
+    pub fn insert(&self, id: T, player: Player) -> Option<Player> {
+        self.players.insert(id, player)
+    }
+
+    pub fn contains<Q>(&self, id: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        self.players.contains_key(id)
+    }
+
+    // 修改排队中的玩家，玩家不在匹配池中时返回false
+    pub fn update<Q>(&self, id: &Q, update: impl FnOnce(&mut Player)) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let Some(mut player) = self.players.get_mut(id) else {
+            return false;
+        };
+        update(player.value_mut());
+        true
+    }
+
+    pub fn remove<Q>(&self, id: &Q) -> Option<Player>
     where
         T: Borrow<Q>,
         Q: Hash + Eq,
     {
+        self.avoid.remove(id);
         self.players.remove(id).map(|(_k, v)| v)
     }
 
+    // 累计一局的对局质量，返回累计以后的结果
+    // 累计的对局质量
+    pub fn quality_stats(&self) -> QualityStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub fn record_quality(&self, quality: &MatchQuality) -> QualityStats {
+        let mut stats = self.stats.lock().unwrap();
+        stats.record(quality);
+        *stats
+    }
+
+    // 设置玩家不想分到同一局的玩家，为空时清除
+    pub fn set_avoid(&self, id: T, avoid: Vec<T>) {
+        if avoid.is_empty() {
+            self.avoid.remove(&id);
+        } else {
+            self.avoid.insert(id, avoid);
+        }
+    }
+
+    // 添加或替换一个房间的补位请求
+    pub fn insert_backfill(&self, stage_request_id: u64, backfill: Backfill) {
+        self.backfills.insert(stage_request_id, backfill);
+    }
+
+    pub fn remove_backfill(&self, stage_request_id: u64) -> Option<Backfill> {
+        self.backfills
+            .remove(&stage_request_id)
+            .map(|(_id, backfill)| backfill)
+    }
+
+    // 删除这个大厅服务器请求的所有补位
+    pub fn remove_backfills_of(&self, sender: &SocketAddr) {
+        self.backfills
+            .retain(|_id, backfill| &backfill.sender != sender);
+    }
+
+    // 所有补位请求，等待时间长的在前
+    pub fn backfills(&self) -> Vec<(u64, Backfill)> {
+        let mut ans = self
+            .backfills
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        ans.sort_by_key(|(_id, backfill)| backfill.anchor.enqueued_at);
+        ans
+    }
+
     // pub fn get<Q>(&self, key: &Q) -> Option<&(usize, usize, usize)>
     // where
     //     T: Borrow<Q>,
@@ -56,12 +465,25 @@ impl<T> Arena<T>
 where
     T: Hash + Eq,
 {
-    pub fn rank_update(&self) {
+    // 按照等待的时间重新计算每位玩家的区间，调用的频率不影响区间的大小
+    pub fn rank_update(&self, config: &ArenaConfig) {
+        let now = Instant::now();
         for mut player in self.players.iter_mut() {
-            let (min_rank_i, max_rank_i, _length, speed) = player.value_mut();
-            *min_rank_i = min_rank_i.saturating_sub(*speed);
-            *max_rank_i = max_rank_i.saturating_add(*speed);
+            let player = player.value_mut();
+            let rank_diff = config.expansion.rank_diff_at(player, now);
+            player.rank_diff = config.cap_rank_diff(rank_diff);
         }
+        for mut backfill in self.backfills.iter_mut() {
+            let anchor = &mut backfill.value_mut().anchor;
+            let rank_diff = config.expansion.rank_diff_at(anchor, now);
+            anchor.rank_diff = config.cap_rank_diff(rank_diff);
+        }
+        // 清除已经过了冷却时间的匹配记录
+        self.recent.retain(|_id, matched| {
+            matched
+                .retain(|(_other, at)| now.saturating_duration_since(*at) < config.recent_cooldown);
+            !matched.is_empty()
+        });
     }
 }
 
@@ -69,106 +491,400 @@ impl<T> Arena<T>
 where
     T: Hash + Eq + Clone + core::fmt::Debug,
 {
-    pub fn rank_match<E: Extend<(T, usize)>>(&self, ans: &mut E) {
-        let players = {
-            let mut players = HashMap::new();
-            // FIXME: 有没有更简单的方法？
-            for player in self.players.iter() {
-                players.insert(player.key().clone(), *player.value());
+    // 复制一份当前的玩家列表，避免在计算时长时间持有DashMap的锁
+    pub fn snapshot(&self) -> Vec<(T, Player)> {
+        self.players
+            .iter()
+            .map(|player| (player.key().clone(), player.value().clone()))
+            .collect()
+    }
+
+    // 记录这些玩家一起匹配过，冷却时间为0时不记录
+    pub fn record_match(&self, ids: &[T], config: &ArenaConfig) {
+        if config.recent_cooldown.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        for id in ids {
+            let others = ids.iter().filter(|other| *other != id);
+            let mut entry = self.recent.entry(id.clone()).or_default();
+            entry.extend(others.map(|other| (other.clone(), now)));
+        }
+    }
+
+    // 小队匹配成功（包括补位和用机器人补满的对局），记录它等待的时间
+    pub fn record_wait(&self, player: &Player) {
+        let wait_secs = player.enqueued_at.elapsed().as_secs();
+        self.waits.lock().unwrap().record(player.rank, wait_secs);
+    }
+
+    // 当前所有不能分到同一局的玩家：互相屏蔽的玩家，以及冷却时间内一起匹配过的玩家
+    pub fn conflicts(&self, config: &ArenaConfig) -> Conflicts<T> {
+        let mut conflicts = Conflicts::new();
+        for entry in self.avoid.iter() {
+            for other in entry.value() {
+                conflicts.add(entry.key(), other);
             }
-            players
-        };
-        let mut max_rank = usize::min_value();
-        let mut min_rank = usize::max_value();
-        for &(min_rank_i, max_rank_i, _length, _speed) in players.values() {
-            max_rank = usize::max(max_rank, max_rank_i);
-            min_rank = usize::min(min_rank, min_rank_i);
-        }
-        if max_rank < min_rank {
-            return; // extend nothing
-        }
-        let mut cnt = vec![0isize; max_rank - min_rank + 2];
-        for &(min_rank_i, max_rank_i, length, _speed) in players.values() {
-            assert!(min_rank_i >= min_rank && min_rank_i <= max_rank);
-            assert!(max_rank_i >= min_rank && max_rank_i <= max_rank);
-            let index_l = min_rank_i - min_rank;
-            let index_r = max_rank_i - min_rank + 1;
-            cnt[index_l] += length as isize;
-            cnt[index_r] -= length as isize;
-        }
-        let mut max_cnt = isize::min_value();
-        let mut max_cnt_i = 0;
-        for i in 1..cnt.len() {
-            cnt[i] += cnt[i - 1];
-            if cnt[i] > max_cnt {
-                max_cnt_i = i;
-                max_cnt = cnt[i];
+        }
+        let now = Instant::now();
+        for entry in self.recent.iter() {
+            for (other, at) in entry.value() {
+                if now.saturating_duration_since(*at) < config.recent_cooldown {
+                    conflicts.add(entry.key(), other);
+                }
             }
         }
-        let target_rank = max_cnt_i + min_rank;
-        let iter = players
+        conflicts
+    }
+
+    // 上一次计算的小队状态，不会重新计算
+    pub fn player_states(&self) -> PlayerStates<T> {
+        Arc::clone(&self.states.lock().unwrap())
+    }
+
+    // 重新计算所有小队的状态，由排位定时器每秒调用一次
+    pub fn refresh_player_states(&self) {
+        let players = self.snapshot();
+        let points = coverage_points(&players);
+        if points.is_empty() {
+            *self.states.lock().unwrap() = Arc::new(Vec::new());
+            return;
+        }
+        // ST表，查询每个玩家的区间内覆盖人数的最大值
+        let mut table = vec![points.iter().map(|&(_rank, cnt)| cnt).collect::<Vec<_>>()];
+        let mut width = 1;
+        while width * 2 <= points.len() {
+            let last = table.last().unwrap();
+            let next = (0..=points.len() - width * 2)
+                .map(|i| usize::max(last[i], last[i + width]))
+                .collect();
+            table.push(next);
+            width *= 2;
+        }
+        // 按进入匹配池的先后排队的位置
+        let mut enqueued = players
             .iter()
-            .filter(|(_, &(min_rank_i, max_rank_i, _, _))| {
-                min_rank_i <= target_rank && target_rank <= max_rank_i
-            })
-            .map(|(id, &(_, _, length, _speed))| (id.clone(), length));
-        ans.extend(iter);
+            .map(|(_id, player)| player.enqueued_at)
+            .collect::<Vec<_>>();
+        enqueued.sort_unstable();
+        let waits = self.waits.lock().unwrap();
+        let iter = players.into_iter().map(|(id, player)| {
+            // 区间有限时玩家自己的左端点一定在points里面，这个范围不会为空；区间是NaN时范围为空，按0报告
+            let l = points.partition_point(|&(rank, _cnt)| rank < player.rank_min());
+            let r = points.partition_point(|&(rank, _cnt)| rank <= player.rank_max());
+            let max_cnt = if r > l {
+                let level = (usize::BITS - 1 - (r - l).leading_zeros()) as usize;
+                usize::max(table[level][l], table[level][r - (1 << level)])
+            } else {
+                0
+            };
+            let wait_secs = player.enqueued_at.elapsed().as_secs();
+            let eta_secs = waits.estimate(player.rank, wait_secs).unwrap_or(0);
+            let position = enqueued.partition_point(|&other| other < player.enqueued_at) + 1;
+            (id, max_cnt as u64, wait_secs, eta_secs, position as u64)
+        });
+        let states = iter.collect();
+        drop(waits);
+        *self.states.lock().unwrap() = Arc::new(states);
+    }
+}
+
+// 可以作为一局中心的分数：区间端点处覆盖人数至少为min_players的分数，覆盖人数多的在前，人数相同时分数低的在前
+pub fn rank_targets<T>(players: &[(T, Player)], min_players: usize) -> Vec<f64> {
+    // 扫描线：只在区间端点处计算覆盖人数，复杂度只和玩家数有关，和分数的范围无关
+    let mut points = coverage_points(players);
+    points.retain(|&(_rank, cnt)| cnt >= min_players);
+    points.sort_by(|(_, a), (_, b)| b.cmp(a));
+    points.into_iter().map(|(rank, _cnt)| rank).collect()
+}
+
+// 返回区间包含target_rank的所有玩家，互相接受模式下只保留其中互相接受的玩家
+pub fn rank_match<T: Clone>(
+    players: &[(T, Player)],
+    target_rank: f64,
+    mutual: bool,
+) -> Vec<(T, Player)> {
+    let matched = players
+        .iter()
+        .filter(|(_, player)| player.rank_min() <= target_rank && target_rank <= player.rank_max())
+        .cloned()
+        .collect::<Vec<_>>();
+    if mutual {
+        mutual_accepted(matched, target_rank)
+    } else {
+        matched
     }
+}
 
-    pub fn get_player_states<E: Extend<(T, u64)>>(&self, ans: &mut E) {
-        let players = {
-            let mut players = HashMap::new();
-            // FIXME: 有没有更简单的方法？
-            for player in self.players.iter() {
-                players.insert(player.key().clone(), *player.value());
+// 互相接受模式下，从区间包含target_rank的玩家里贪心地选出一组互相接受的玩家
+// 分数离target_rank近的玩家优先。一组玩家互相接受，当且仅当所有人的分数都在所有人区间的交集里面，
+// 所以只需要维护已选玩家的分数范围和区间的交集。这组玩家的任意子集也都是互相接受的
+pub fn mutual_accepted<T>(mut players: Vec<(T, Player)>, target_rank: f64) -> Vec<(T, Player)> {
+    players.sort_by(|(_, a), (_, b)| {
+        f64::total_cmp(&(a.rank - target_rank).abs(), &(b.rank - target_rank).abs())
+    });
+    let (mut rank_lo, mut rank_hi) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut window_lo, mut window_hi) = (f64::NEG_INFINITY, f64::INFINITY);
+    players.retain(|(_, player)| {
+        let new_rank_lo = f64::min(rank_lo, player.rank);
+        let new_rank_hi = f64::max(rank_hi, player.rank);
+        let new_window_lo = f64::max(window_lo, player.rank_min());
+        let new_window_hi = f64::min(window_hi, player.rank_max());
+        if new_window_lo <= new_rank_lo && new_rank_hi <= new_window_hi {
+            (rank_lo, rank_hi) = (new_rank_lo, new_rank_hi);
+            (window_lo, window_hi) = (new_window_lo, new_window_hi);
+            true
+        } else {
+            false
+        }
+    });
+    players
+}
+
+// 计算每个区间左端点处被多少位玩家的区间覆盖，返回按分数排序的(分数, 覆盖人数)
+// 覆盖人数的最大值一定出现在某个区间的左端点上
+fn coverage_points<T>(players: &[(T, Player)]) -> Vec<(f64, usize)> {
+    // (分数, 0为进入区间 1为离开区间, 人数)。区间是闭区间，同一个分数上先进入后离开
+    let mut events = Vec::with_capacity(players.len() * 2);
+    for (_, player) in players {
+        events.push((player.rank_min(), 0, player.length));
+        events.push((player.rank_max(), 1, player.length));
+    }
+    events
+        .sort_unstable_by(|a: &(f64, u8, usize), b| f64::total_cmp(&a.0, &b.0).then(a.1.cmp(&b.1)));
+    let mut points: Vec<(f64, usize)> = Vec::with_capacity(players.len());
+    let mut cnt = 0;
+    for (rank, kind, length) in events {
+        if kind == 0 {
+            cnt += length;
+            match points.last_mut() {
+                Some(last) if last.0 == rank => last.1 = cnt,
+                _ => points.push((rank, cnt)),
             }
+        } else {
+            cnt -= length;
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{config, player};
+    use rand::Rng;
+
+    // 整数分数和区间，端点经常重合
+    fn random_players(rng: &mut impl Rng) -> Vec<(usize, Player)> {
+        let n = rng.gen_range(0..12);
+        (0..n)
+            .map(|i| {
+                let rank = rng.gen_range(0..20) as f64;
+                let rank_diff = rng.gen_range(0..5) as f64;
+                (i, player(rank, rank_diff, rng.gen_range(1..4), 0))
+            })
+            .collect()
+    }
+
+    // 区间包含rank的玩家人数
+    fn brute_coverage(players: &[(usize, Player)], rank: f64) -> usize {
+        players
+            .iter()
+            .filter(|(_, player)| player.rank_min() <= rank && rank <= player.rank_max())
+            .map(|(_, player)| player.length)
+            .sum()
+    }
+
+    fn mutually_accepted(players: &[&Player]) -> bool {
+        players.iter().all(|a| {
             players
+                .iter()
+                .all(|b| a.rank_min() <= b.rank && b.rank <= a.rank_max())
+        })
+    }
+
+    #[test]
+    fn coverage_on_shared_endpoints() {
+        // 闭区间，端点重合的两个区间在这个分数上都算覆盖
+        let players = vec![
+            (0, player(5.0, 5.0, 1, 0)),
+            (1, player(15.0, 5.0, 2, 0)),
+            (2, player(10.0, 0.0, 1, 0)),
+            (3, player(10.0, 0.0, 1, 0)),
+        ];
+        assert_eq!(coverage_points(&players), vec![(0.0, 1), (10.0, 5)]);
+        assert_eq!(rank_targets(&players, 0), vec![10.0, 0.0]);
+        assert_eq!(rank_targets(&players, 2), vec![10.0]);
+        let ids = |matched: Vec<(usize, Player)>| {
+            matched.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
         };
+        assert_eq!(ids(rank_match(&players, 10.0, false)), vec![0, 1, 2, 3]);
+        // 0和1的分数不在对方的区间内，只留下分数离10最近的玩家
+        assert_eq!(ids(rank_match(&players, 10.0, true)), vec![2, 3]);
+    }
+
+    #[test]
+    fn random_coverage_against_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..2000 {
+            let players = random_players(&mut rng);
+            let points = coverage_points(&players);
+            // 每个左端点恰好出现一次，按分数排序，覆盖人数和逐个数出来的相同
+            let mut lefts = players
+                .iter()
+                .map(|(_, player)| player.rank_min())
+                .collect::<Vec<_>>();
+            lefts.sort_by(f64::total_cmp);
+            lefts.dedup();
+            let ranks = points.iter().map(|&(rank, _)| rank).collect::<Vec<_>>();
+            assert_eq!(ranks, lefts, "{players:?}");
+            for &(rank, cnt) in &points {
+                assert_eq!(cnt, brute_coverage(&players, rank), "{players:?}");
+            }
+            // 覆盖人数的最大值和在所有端点上逐个数出来的最大值相同
+            let best = players
+                .iter()
+                .flat_map(|(_, player)| [player.rank_min(), player.rank_max()])
+                .map(|rank| brute_coverage(&players, rank))
+                .max();
+            let targets = rank_targets(&players, 0);
+            let target_best = targets.first().map(|&rank| brute_coverage(&players, rank));
+            assert_eq!(target_best, best, "{players:?}");
+            let coverages = targets
+                .iter()
+                .map(|&rank| brute_coverage(&players, rank))
+                .collect::<Vec<_>>();
+            assert!(coverages.windows(2).all(|w| w[0] >= w[1]), "{players:?}");
+        }
+    }
 
-        let mut max_rank = usize::min_value();
-        let mut min_rank = usize::max_value();
-        for &(min_rank_i, max_rank_i, _length, _speed) in players.values() {
-            max_rank = usize::max(max_rank, max_rank_i);
-            min_rank = usize::min(min_rank, min_rank_i);
-        }
-
-        if max_rank < min_rank {
-            return; // extend nothing and return
-        }
-
-        let mut cnt = vec![0isize; max_rank - min_rank + 2];
-        let mut player_idx_l = vec![HashSet::new(); max_rank - min_rank + 2];
-        let mut player_idx_r = vec![HashSet::new(); max_rank - min_rank + 2];
-        for (id, &(min_rank_i, max_rank_i, length, _speed)) in players.iter() {
-            assert!(min_rank_i >= min_rank && min_rank_i <= max_rank);
-            assert!(max_rank_i >= min_rank && max_rank_i <= max_rank);
-            let index_l = min_rank_i - min_rank;
-            let index_r = max_rank_i - min_rank + 1;
-            assert!(/*index_l >= 0 && */ index_l < max_rank - min_rank + 2);
-            assert!(/*index_r >= 0 && */ index_r < max_rank - min_rank + 2);
-            cnt[index_l] += length as isize;
-            cnt[index_r] -= length as isize;
-            player_idx_l[index_l].insert((id.clone(), length));
-            player_idx_r[index_r].insert((id.clone(), length));
-        }
-
-        for i in 1..cnt.len() {
-            cnt[i] += cnt[i - 1];
-        }
-
-        let mut cur_players = HashSet::new();
-        let mut res = HashMap::new();
-        for (idx, &cnt_i) in cnt.iter().enumerate() {
-            // println!("idx = {idx}, cnt_i = {cnt_i}, players = {:?}", cur_players);
-            cur_players.extend(player_idx_l[idx].iter());
-            for &(id, _length) in cur_players.iter() {
-                res.entry(id.clone())
-                    .and_modify(|e| *e = u64::max(*e, cnt_i as u64))
-                    .or_insert(0);
+    #[test]
+    fn random_rank_match_against_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..2000 {
+            let players = random_players(&mut rng);
+            for target in rank_targets(&players, 0) {
+                let covering = players
+                    .iter()
+                    .filter(|(_, player)| {
+                        player.rank_min() <= target && target <= player.rank_max()
+                    })
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+                let matched = rank_match(&players, target, false);
+                let ids = matched.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+                assert_eq!(ids, covering, "{players:?} {target}");
+                // 互相接受模式：选出的玩家互相接受，并且剩下的玩家加进来都会破坏互相接受
+                let matched = rank_match(&players, target, true);
+                assert!(!matched.is_empty());
+                let chosen = matched.iter().map(|(_, player)| player).collect::<Vec<_>>();
+                assert!(mutually_accepted(&chosen), "{players:?} {target}");
+                for id in &covering {
+                    if matched.iter().any(|(other, _)| other == id) {
+                        continue;
+                    }
+                    let mut extended = chosen.clone();
+                    extended.push(&players[*id].1);
+                    assert!(!mutually_accepted(&extended), "{players:?} {target}");
+                }
             }
-            cur_players.retain(|e| player_idx_r[idx].get(e).is_none());
         }
+    }
+
+    // 逐个玩家扫描：区间内所有左端点上覆盖人数的最大值
+    fn naive_max_coverage(players: &[(usize, Player)], player: &Player) -> u64 {
+        players
+            .iter()
+            .map(|(_, other)| other.rank_min())
+            .filter(|&rank| player.rank_min() <= rank && rank <= player.rank_max())
+            .map(|rank| brute_coverage(players, rank))
+            .max()
+            .unwrap() as u64
+    }
 
-        ans.extend(res)
+    fn check_player_states(players: &[(usize, Player)]) {
+        let arena = Arena::new();
+        for (id, player) in players {
+            arena.insert(*id, player.clone());
+        }
+        arena.refresh_player_states();
+        let states = arena.player_states();
+        assert_eq!(states.len(), players.len());
+        for (id, num_matched, _wait_secs, _eta_secs, _position) in states.iter() {
+            let expected = naive_max_coverage(players, &players[*id].1);
+            assert_eq!(*num_matched, expected, "{players:?} {id}");
+        }
+    }
+
+    #[test]
+    fn validate_config() {
+        assert_eq!(config(4, 2).validate(), Ok(()));
+        assert!(config(0, 1).validate().is_err());
+        assert!(config(5, 2).validate().is_err());
+        let roles = ArenaConfig {
+            roles: vec![("tank".to_string(), 1), ("dps".to_string(), 1)],
+            ..config(4, 2)
+        };
+        assert_eq!(roles.validate(), Ok(()));
+        let duplicate_role = ArenaConfig {
+            roles: vec![("tank".to_string(), 1), ("tank".to_string(), 1)],
+            ..config(4, 2)
+        };
+        assert!(duplicate_role.validate().is_err());
+        let bots_with_roles = ArenaConfig {
+            bots: Some(BotFill {
+                after: Duration::from_secs(10),
+                min_players: 1,
+            }),
+            ..roles
+        };
+        assert!(bots_with_roles.validate().is_err());
+    }
+
+    #[test]
+    fn player_states_single_and_identical() {
+        check_player_states(&[]);
+        check_player_states(&[(0, player(1000.0, 0.0, 3, 0))]);
+        let identical = (0..5)
+            .map(|i| (i, player(1000.0, 50.0, 1, 0)))
+            .collect::<Vec<_>>();
+        check_player_states(&identical);
+        let arena = Arena::new();
+        for (id, player) in &identical {
+            arena.insert(*id, player.clone());
+        }
+        arena.refresh_player_states();
+        assert!(arena.player_states().iter().all(|state| state.1 == 5));
+    }
+
+    #[test]
+    fn player_states_with_nan_window() {
+        let arena = Arena::new();
+        arena.insert(0, player(1000.0, 50.0, 1, 0));
+        arena.insert(1, player(1000.0, f64::NAN, 1, 0));
+        arena.refresh_player_states();
+        let states = arena.player_states();
+        assert_eq!(states.len(), 2);
+        assert!(states.iter().any(|state| state.0 == 1 && state.1 == 0));
+    }
+
+    #[test]
+    fn random_player_states_against_naive_scan() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..500 {
+            check_player_states(&random_players(&mut rng));
+        }
+        // 玩家多时ST表有更多层
+        for _ in 0..20 {
+            let n = rng.gen_range(50..200);
+            let players = (0..n)
+                .map(|i| {
+                    let rank = rng.gen_range(0..500) as f64;
+                    let rank_diff = rng.gen_range(0..80) as f64;
+                    (i, player(rank, rank_diff, rng.gen_range(1..3), 0))
+                })
+                .collect::<Vec<_>>();
+            check_player_states(&players);
+        }
     }
 }
@@ -0,0 +1,184 @@
+// 属性筛选：玩家带上地图、模式、语言等属性，一局的所有小队必须在匹配池规定的属性上取得一致，
+// 这样不用为每种组合单独注册匹配池
+use crate::arena::Player;
+use std::time::{Duration, Instant};
+
+// 每次组局最多尝试多少种属性取值的组合，组合太多时只尝试等待时间长的小队想要的
+pub const MAX_ASSIGNMENTS: usize = 64;
+
+// 匹配池对一个属性的要求
+#[derive(Clone, Debug)]
+pub struct AttributeRule {
+    pub key: String,
+    pub kind: AttributeKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AttributeKind {
+    // 只看小队的第一个取值，一局的所有小队必须相同，例如语言
+    Exact,
+    // 小队可以接受多个取值，一局的所有小队至少有一个共同的取值，例如地图
+    Overlap,
+    // 和Overlap相同，但小队等待超过relax以后不再要求
+    Soft { relax: Duration },
+}
+
+// 一种组合：和匹配池的属性规则一一对应，None表示这一局不限制这个属性
+pub type Assignment = Vec<Option<String>>;
+
+// 小队在这个属性上现在可以接受的取值，为空表示可以接受任何取值
+fn active_values<'a>(rule: &AttributeRule, player: &'a Player, now: Instant) -> &'a [String] {
+    let values = player
+        .attributes
+        .iter()
+        .find(|(key, _values)| key == &rule.key)
+        .map(|(_key, values)| values.as_slice())
+        .unwrap_or_default();
+    match rule.kind {
+        AttributeKind::Exact => &values[..values.len().min(1)],
+        AttributeKind::Overlap => values,
+        AttributeKind::Soft { relax } => {
+            if now.saturating_duration_since(player.enqueued_at) >= relax {
+                &[]
+            } else {
+                values
+            }
+        }
+    }
+}
+
+// 按顺序列出小队想要的所有组合，排在前面的小队和小队靠前的取值先尝试，重复的组合只保留第一次出现
+pub fn assignments<'a>(
+    rules: &[AttributeRule],
+    players: impl IntoIterator<Item = &'a Player>,
+    now: Instant,
+) -> Vec<Assignment> {
+    let mut ans: Vec<Assignment> = Vec::new();
+    for player in players {
+        let mut combos: Vec<Assignment> = vec![Vec::new()];
+        for rule in rules {
+            let values = active_values(rule, player, now);
+            let options = if values.is_empty() {
+                vec![None]
+            } else {
+                values.iter().cloned().map(Some).collect()
+            };
+            combos = combos
+                .iter()
+                .flat_map(|combo| {
+                    options.iter().map(move |option| {
+                        let mut combo = combo.clone();
+                        combo.push(option.clone());
+                        combo
+                    })
+                })
+                .take(MAX_ASSIGNMENTS)
+                .collect();
+        }
+        for combo in combos {
+            if ans.len() >= MAX_ASSIGNMENTS {
+                return ans;
+            }
+            if !ans.contains(&combo) {
+                ans.push(combo);
+            }
+        }
+    }
+    ans
+}
+
+// 小队能否参加按这种组合开的一局。限制的属性要在小队可以接受的取值里，
+// 不限制的属性要求小队也可以接受任何取值，否则这个小队应当在限制了这个属性的组合里匹配
+pub fn accepts(
+    rules: &[AttributeRule],
+    assignment: &Assignment,
+    player: &Player,
+    now: Instant,
+) -> bool {
+    rules.iter().zip(assignment).all(|(rule, value)| {
+        let values = active_values(rule, player, now);
+        match value {
+            Some(value) => values.is_empty() || values.contains(value),
+            None => values.is_empty(),
+        }
+    })
+}
+
+// 这一局取得一致的属性，(属性名称, 取值)
+pub fn agreed(rules: &[AttributeRule], assignment: &Assignment) -> Vec<(String, String)> {
+    rules
+        .iter()
+        .zip(assignment)
+        .filter_map(|(rule, value)| Some((rule.key.clone(), value.clone()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{player, with_attributes};
+
+    fn rules() -> Vec<AttributeRule> {
+        vec![
+            AttributeRule {
+                key: "lang".to_string(),
+                kind: AttributeKind::Exact,
+            },
+            AttributeRule {
+                key: "map".to_string(),
+                kind: AttributeKind::Soft {
+                    relax: Duration::from_secs(30),
+                },
+            },
+        ]
+    }
+
+    #[test]
+    fn assignments_in_preference_order() {
+        let now = Instant::now();
+        let a = with_attributes(
+            player(0.0, 0.0, 1, 0),
+            &[("lang", &["zh", "en"]), ("map", &["m1", "m2"])],
+        );
+        let b = with_attributes(
+            player(0.0, 0.0, 1, 0),
+            &[("lang", &["en"]), ("map", &["m2"])],
+        );
+        let ans = assignments(&rules(), [&a, &b], now);
+        let some = |lang: &str, map: &str| vec![Some(lang.to_string()), Some(map.to_string())];
+        assert_eq!(
+            ans,
+            vec![some("zh", "m1"), some("zh", "m2"), some("en", "m2")]
+        );
+        assert!(accepts(&rules(), &ans[1], &a, now));
+        assert!(!accepts(&rules(), &ans[1], &b, now));
+        assert!(accepts(&rules(), &ans[2], &b, now));
+    }
+
+    #[test]
+    fn soft_preference_relaxes() {
+        let now = Instant::now();
+        let waiting = with_attributes(
+            player(0.0, 0.0, 1, 40),
+            &[("lang", &["zh"]), ("map", &["m1"])],
+        );
+        let fresh = with_attributes(
+            player(0.0, 0.0, 1, 0),
+            &[("lang", &["zh"]), ("map", &["m2"])],
+        );
+        let assignment = vec![Some("zh".to_string()), Some("m2".to_string())];
+        assert!(accepts(&rules(), &assignment, &waiting, now));
+        assert!(accepts(&rules(), &assignment, &fresh, now));
+        // 不限制地图的组合只接受不挑地图的小队
+        let any_map = vec![Some("zh".to_string()), None];
+        assert!(accepts(&rules(), &any_map, &waiting, now));
+        assert!(!accepts(&rules(), &any_map, &fresh, now));
+        assert_eq!(
+            agreed(&rules(), &assignment),
+            vec![
+                ("lang".to_string(), "zh".to_string()),
+                ("map".to_string(), "m2".to_string())
+            ]
+        );
+    }
+}
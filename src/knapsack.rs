@@ -0,0 +1,212 @@
+// 背包问题（子集和）求解器：从若干个小队中选出一些，使人数之和恰好等于某个目标人数
+
+// 定长的位集合，第i位表示人数i能否凑出来
+#[derive(Clone)]
+struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    fn get(&self, i: usize) -> bool {
+        i < self.len && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, i: usize) {
+        if i < self.len {
+            self.words[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    // 返回 self | (self << shift)，超出长度的位直接丢弃
+    fn or_shifted(&self, shift: usize) -> BitSet {
+        let mut ans = self.clone();
+        let (word_shift, bit_shift) = (shift / 64, shift % 64);
+        for i in (word_shift..self.words.len()).rev() {
+            let src = i - word_shift;
+            let mut word = self.words[src] << bit_shift;
+            if bit_shift != 0 && src >= 1 {
+                word |= self.words[src - 1] >> (64 - bit_shift);
+            }
+            ans.words[i] |= word;
+        }
+        // 清掉最后一个字里超出长度的位
+        if !self.len.is_multiple_of(64) {
+            if let Some(last) = ans.words.last_mut() {
+                *last &= (1 << (self.len % 64)) - 1;
+            }
+        }
+        ans
+    }
+}
+
+// lengths是每个小队的人数，按优先级从高到低排列。调用者决定优先级，例如等待时间长的在前，
+// 或者分数离目标近的在前（这样选出来的分数跨度小）
+// 从max_sum往下找第一个能凑出来、并且accept返回true的人数作为目标人数
+// 返回(目标人数, 选中的小队下标，从小到大)。凑不出来时返回None
+// 所有和为目标人数的方案里，优先级最高的小队只要能被选中就一定会被选中，
+// 然后在这个前提下尽量选中优先级第二高的，依此类推
+// 时间复杂度O(n × max_sum / 64)，内存为(n + 1) × (max_sum + 1)位
+pub fn solve<F>(lengths: &[usize], max_sum: usize, accept: F) -> Option<(usize, Vec<usize>)>
+where
+    F: Fn(usize) -> bool,
+{
+    // reach[i]：只用第i个及以后的小队，可以凑出哪些人数
+    let mut reach = vec![BitSet::new(max_sum + 1); lengths.len() + 1];
+    reach[lengths.len()].set(0);
+    for i in (0..lengths.len()).rev() {
+        reach[i] = reach[i + 1].or_shifted(lengths[i]);
+    }
+    let target = (0..=max_sum)
+        .rev()
+        .find(|&sum| reach[0].get(sum) && accept(sum))?;
+    // 从前往后贪心：第i个小队能选就选，选了以后剩下的人数还要能用后面的小队凑出来
+    let mut rest = target;
+    let mut chosen = Vec::new();
+    for (i, &length) in lengths.iter().enumerate() {
+        if length <= rest && reach[i + 1].get(rest - length) {
+            chosen.push(i);
+            rest -= length;
+        }
+    }
+    debug_assert_eq!(rest, 0);
+    Some((target, chosen))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn exact_sum() {
+        assert_eq!(
+            solve(&[1, 1, 1, 1], 4, |n| n == 4),
+            Some((4, vec![0, 1, 2, 3]))
+        );
+        assert_eq!(solve(&[2, 3, 1, 2], 4, |n| n == 4), Some((4, vec![0, 3])));
+    }
+
+    #[test]
+    fn impossible() {
+        assert_eq!(solve(&[3, 3, 3], 4, |n| n == 4), None);
+        assert_eq!(solve(&[], 4, |n| n == 4), None);
+        assert_eq!(solve(&[1, 1], 4, |n| n == 4), None);
+    }
+
+    #[test]
+    fn party_larger_than_target() {
+        assert_eq!(solve(&[5, 2, 2], 4, |n| n == 4), Some((4, vec![1, 2])));
+    }
+
+    #[test]
+    fn higher_priority_first() {
+        // 第0个小队只能和第3个小队一起凑出4人
+        assert_eq!(solve(&[3, 2, 2, 1], 4, |n| n == 4), Some((4, vec![0, 3])));
+        // 第0个小队怎么都选不上时，优先选第1个
+        assert_eq!(
+            solve(&[4, 1, 3, 3, 1], 2, |n| n == 2),
+            Some((2, vec![1, 4]))
+        );
+    }
+
+    #[test]
+    fn largest_accepted_sum() {
+        assert_eq!(
+            solve(&[2, 2, 2, 3], 10, |n| n >= 6),
+            Some((9, vec![0, 1, 2, 3]))
+        );
+        assert_eq!(
+            solve(&[2, 2, 2, 3], 10, |n| n.is_multiple_of(2)),
+            Some((6, vec![0, 1, 2]))
+        );
+    }
+
+    #[test]
+    fn crosses_word_boundary() {
+        let lengths = vec![7; 30];
+        let (sum, chosen) = solve(&lengths, 200, |_| true).unwrap();
+        assert_eq!(sum, 196);
+        assert_eq!(chosen, (0..28).collect::<Vec<_>>());
+    }
+
+    // 枚举所有子集，找出和最大的可接受人数，以及这个人数下按优先级依次尽量选中的方案
+    fn brute_force(
+        lengths: &[usize],
+        max_sum: usize,
+        accept: impl Fn(usize) -> bool,
+    ) -> Option<(usize, Vec<usize>)> {
+        let mut best: Option<(usize, Vec<bool>)> = None;
+        for mask in 0u32..(1 << lengths.len()) {
+            // 按下标依次比较是否选中，选中的方案更好
+            let selected = (0..lengths.len())
+                .map(|i| mask & (1 << i) != 0)
+                .collect::<Vec<_>>();
+            let sum: usize = (0..lengths.len())
+                .filter(|&i| selected[i])
+                .map(|i| lengths[i])
+                .sum();
+            if sum > max_sum || !accept(sum) {
+                continue;
+            }
+            if best.as_ref().is_none_or(|(best_sum, best_selected)| {
+                (sum, &selected) > (*best_sum, best_selected)
+            }) {
+                best = Some((sum, selected));
+            }
+        }
+        best.map(|(sum, selected)| (sum, (0..lengths.len()).filter(|&i| selected[i]).collect()))
+    }
+
+    #[test]
+    fn random_against_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..2000 {
+            let n = rng.gen_range(0..12);
+            let lengths = (0..n).map(|_| rng.gen_range(0..6)).collect::<Vec<_>>();
+            let max_sum = rng.gen_range(0..20);
+            let min_sum = rng.gen_range(0..=max_sum);
+            let teams = rng.gen_range(1..4);
+            let accept = |sum: usize| sum >= min_sum && sum.is_multiple_of(teams);
+            let ans = solve(&lengths, max_sum, accept);
+            assert_eq!(
+                ans,
+                brute_force(&lengths, max_sum, accept),
+                "lengths = {lengths:?}, max_sum = {max_sum}"
+            );
+        }
+    }
+
+    #[test]
+    fn random_large_sums() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let n = rng.gen_range(1..200);
+            let lengths = (0..n).map(|_| rng.gen_range(1..5)).collect::<Vec<_>>();
+            let target = rng.gen_range(1..300);
+            // 普通的布尔数组动态规划，只判断能不能凑出来
+            let mut can = vec![false; target + 1];
+            can[0] = true;
+            for &length in &lengths {
+                for sum in (length..=target).rev() {
+                    can[sum] |= can[sum - length];
+                }
+            }
+            match solve(&lengths, target, |n| n == target) {
+                Some((sum, chosen)) => {
+                    assert_eq!(sum, target);
+                    assert_eq!(chosen.iter().map(|&i| lengths[i]).sum::<usize>(), target);
+                    assert!(chosen.windows(2).all(|w| w[0] < w[1]));
+                }
+                None => assert!(!can[target]),
+            }
+        }
+    }
+}
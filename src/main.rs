@@ -1,15 +1,40 @@
 mod arena;
+mod attribute;
+mod knapsack;
 mod packet;
+mod quality;
+mod rating;
+mod role;
+mod strategy;
+mod team;
+#[cfg(test)]
+mod test_util;
+mod wait;
 
-use arena::Arena;
+use arena::{
+    Arena, ArenaConfig, Backfill, BotFill, DrawCriterion, Expansion, Latency, Player, Priority,
+};
+use attribute::{AttributeKind, AttributeRule};
 use config::{Config, ConfigError, File, FileFormat};
 use dashmap::DashMap;
 use futures_channel::mpsc::{self, UnboundedSender};
 use futures_util::{future, pin_mut, stream::TryStreamExt, StreamExt};
 use lazy_static::lazy_static;
 use lockfree_cuckoohash::LockFreeCuckooHash;
-use packet::Packet;
-use std::{net::SocketAddr, str::FromStr, sync::Arc};
+use packet::{ArenaStats, MatchedParty, Packet, PlayerState};
+use quality::MatchQuality;
+use rating::{RatingStore, RatingSystem};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use strategy::{Bot, Group, MatchStrategy};
 use tokio::{
     net::{TcpListener, TcpStream},
     time,
@@ -19,11 +44,63 @@ use tungstenite::protocol::Message;
 // 客户端，也就是大厅服务器
 type Tx = UnboundedSender<Message>;
 type Peers = Arc<LockFreeCuckooHash<SocketAddr, Tx>>;
-// 哪个玩家是哪个大厅服务器记录的
-type Senders = Arc<dashmap::DashMap<String, SocketAddr>>;
+// 每位玩家在哪些匹配池排队，以及在每个匹配池是哪个大厅服务器添加的
+type Senders = Arc<dashmap::DashMap<String, Vec<(String, SocketAddr)>>>;
+
+// 所有匹配池的列表，以及每个匹配池的配置和匹配策略
+type Arenas =
+    Arc<dashmap::DashMap<String, (ArenaConfig, Arena<String>, Arc<dyn MatchStrategy<String>>)>>;
+
+// 已经创建房间、等待比赛结果的对局，以及按比赛结果更新的评分
+type Results = Arc<MatchResults>;
+
+// 超过这个时间还没有收到比赛结果的对局不再等待
+const RESULT_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+// 匹配成功、还没有创建房间的一局
+struct FormedMatch {
+    region: Option<String>,
+    quality: MatchQuality,
+    // (添加小队的大厅服务器, 小队)
+    parties: Vec<(SocketAddr, MatchedParty)>,
+    bots: Vec<Bot>,
+    // 这一局取得一致的属性，(属性名称, 取值)
+    attributes: Vec<(String, String)>,
+}
+
+// 等待所有小队确认的对局，按pending_id索引
+type Pendings = Arc<dashmap::DashMap<u64, Pending>>;
+
+static NEXT_PENDING_ID: AtomicU64 = AtomicU64::new(1);
 
-// 所有匹配池的列表。u64是这个匹配池一局的玩家数，超过这个数就匹配成功
-type Arenas = Arc<dashmap::DashMap<String, (u64, Arena<String>)>>;
+struct Pending {
+    arena: String,
+    formed: FormedMatch,
+    // 每位玩家从排队表取出的所有(匹配池, 大厅服务器地址)。开局时才从其他匹配池删除，取消时原样放回
+    claimed: Vec<(String, Vec<(String, SocketAddr)>)>,
+    // 已经接受和拒绝的小队
+    accepted: HashSet<String>,
+    declined: HashSet<String>,
+    // 超过这个时间还没有回复的小队当作拒绝
+    deadline: Instant,
+}
+
+#[derive(Default)]
+struct MatchResults {
+    // 等待比赛结果的对局，按stage_request_id索引
+    records: DashMap<u64, MatchRecord>,
+    // 每个匹配池的评分，按匹配池名称索引。删除匹配池时保留，重新注册同名的匹配池以后继续使用
+    ratings: DashMap<String, RatingStore<String>>,
+}
+
+struct MatchRecord {
+    arena: String,
+    // (玩家, 小队人数, 队伍编号)
+    parties: Vec<(String, usize, usize)>,
+    // 添加了这一局小队的大厅服务器，只接受它们报告的比赛结果
+    lobbies: HashSet<SocketAddr>,
+    created_at: Instant,
+}
 
 // 全局的配置文件
 fn load_config() -> Result<Config, ConfigError> {
@@ -41,6 +118,8 @@ async fn handle_connection(
     peer_map: Peers,
     arenas: Arenas,
     senders: Senders,
+    results: Results,
+    pendings: Pendings,
     raw_stream: TcpStream,
     addr: SocketAddr,
 ) {
@@ -73,13 +152,133 @@ async fn handle_connection(
         let text = msg.to_text().unwrap();
         let packet = Packet::from_str(text);
         match packet {
-            Ok(Packet::AddArena { arena , num_players }) => {
-                if num_players == 0 {
-                    println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但匹配池的每局玩家数为0，创建失败！");
-                } else {
+            Ok(Packet::AddArena {
+                arena,
+                num_players,
+                num_teams,
+                mutual,
+                expansion,
+                expansion_ratio,
+                mut expansion_steps,
+                max_rank_diff,
+                min_players,
+                fill_after_secs,
+                priority,
+                strategy,
+                roles,
+                latency_threshold,
+                latency_speed,
+                max_latency_threshold,
+                recent_cooldown_secs,
+                rating_system,
+                rating_param,
+                draw_beta,
+                min_draw_quality,
+                draw_relax_secs,
+                accept_timeout_secs,
+                bot_fill_after_secs,
+                bot_min_players,
+                attributes,
+            }) => {
+                let num_teams = u64::max(num_teams, 1);
+                let min_players = if min_players == 0 { num_players } else { min_players };
+                expansion_steps.sort_unstable_by_key(|&(secs, _diff)| secs);
+                let try_expansion = match expansion {
+                    0 => Some(Expansion::Linear),
+                    1 => Some(Expansion::Exponential { ratio: expansion_ratio as usize }),
+                    2 => Some(Expansion::Steps(
+                        expansion_steps
+                            .iter()
+                            .map(|&(secs, diff)| (secs, if diff == 0.0 { None } else { Some(diff) }))
+                            .collect(),
+                    )),
+                    _ => None,
+                };
+                let try_priority = match priority {
+                    0 => Some(Priority::LongestWait),
+                    1 => Some(Priority::SmallestSpread),
+                    2 => Some(Priority::DrawProbability),
+                    _ => None,
+                };
+                let try_strategy = strategy::by_name(&strategy);
+                let try_rating = match rating_system {
+                    0 => Some(None),
+                    1 => Some(Some(RatingSystem::Elo { k: if rating_param == 0 { 32.0 } else { rating_param as f64 } })),
+                    2 => Some(Some(RatingSystem::Glicko2 { tau: if rating_param == 0 { 0.5 } else { rating_param as f64 / 1000.0 } })),
+                    _ => None,
+                };
+                let try_attributes = attributes
+                    .iter()
+                    .map(|(key, kind, relax_secs)| {
+                        let kind = match (kind, relax_secs) {
+                            (0, _) => AttributeKind::Exact,
+                            (1, _) => AttributeKind::Overlap,
+                            (2, 1..) => AttributeKind::Soft { relax: Duration::from_secs(*relax_secs) },
+                            _ => return None,
+                        };
+                        Some(AttributeRule { key: key.clone(), kind })
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if try_expansion.is_none() {
+                    println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但区间扩散方式 {expansion} 不存在，创建失败！");
+                } else if try_priority.is_none() {
+                    println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但优先选择方式 {priority} 不存在，创建失败！");
+                } else if try_strategy.is_none() {
+                    println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但匹配策略 {strategy} 不存在，创建失败！");
+                } else if try_rating.is_none() {
+                    println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但评分系统 {rating_system} 不存在，创建失败！");
+                } else if try_attributes.is_none() {
+                    println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但属性要求 {attributes:?} 中有不存在的要求，或者软性要求的放宽秒数为0，创建失败！");
+                } else if let (Some(expansion), Some(priority), Some(try_strategy), Some(rating), Some(attributes)) = (try_expansion, try_priority, try_strategy, try_rating, try_attributes) {
+                    let config = ArenaConfig {
+                        min_players: min_players as usize,
+                        max_players: num_players as usize,
+                        fill_after: Duration::from_secs(fill_after_secs),
+                        num_teams: num_teams as usize,
+                        mutual: mutual != 0,
+                        expansion,
+                        max_rank_diff: if max_rank_diff > 0.0 { Some(max_rank_diff) } else { None },
+                        priority,
+                        roles: roles.into_iter().map(|(role, count)| (role, count as usize)).collect(),
+                        latency: if latency_threshold == 0 {
+                            None
+                        } else {
+                            Some(Latency {
+                                init: latency_threshold,
+                                speed: latency_speed,
+                                max: if max_latency_threshold == 0 { None } else { Some(max_latency_threshold) },
+                            })
+                        },
+                        recent_cooldown: Duration::from_secs(recent_cooldown_secs),
+                        rating,
+                        draw: if draw_beta <= 0.0 {
+                            None
+                        } else {
+                            Some(DrawCriterion {
+                                beta: draw_beta,
+                                min_quality: min_draw_quality as f64 / 1000.0,
+                                relax: Duration::from_secs(draw_relax_secs),
+                            })
+                        },
+                        accept_timeout: if accept_timeout_secs == 0 { None } else { Some(Duration::from_secs(accept_timeout_secs)) },
+                        bots: if bot_fill_after_secs == 0 {
+                            None
+                        } else {
+                            Some(BotFill {
+                                after: Duration::from_secs(bot_fill_after_secs),
+                                min_players: usize::max(bot_min_players as usize, 1),
+                            })
+                        },
+                        attributes,
+                    };
+                    if let Err(error) = config.validate() {
+                        println!("[匹配池]({addr}) 尝试注册匹配池 {arena}，但{error}，创建失败！配置：{config:?}");
+                        return future::ok(());
+                    }
+                    println!("[匹配池]({addr}) 已注册匹配池 {arena}，达到 {num_players} 位玩家时（有玩家等待超过 {fill_after_secs} 秒后只需 {min_players} 位），此匹配池将返回匹配结果，玩家将被分成 {num_teams} 个队伍。互相接受模式：{}，区间扩散方式：{:?}，区间半径上限：{:?}，优先选择：{:?}，匹配策略：{:?}，角色位置：{:?}，地区延迟阈值：{:?}，再次匹配的冷却时间：{recent_cooldown_secs} 秒，评分系统：{:?}，平局概率标准：{:?}，确认时间：{:?}，机器人补位：{:?}，属性要求：{:?}",
+                        config.mutual, config.expansion, config.max_rank_diff, config.priority, try_strategy, config.roles, config.latency, config.rating, config.draw, config.accept_timeout, config.bots, config.attributes);
                     let entry = arenas.entry(arena.clone());
-                    entry.or_insert_with(|| (num_players, Arena::new()));
-                    println!("[匹配池]({addr}) 已注册匹配池 {arena}，达到 {num_players} 位玩家时，此匹配池将返回匹配结果。");
+                    entry.or_insert_with(|| (config, Arena::new(), try_strategy));
                 }
             },
             Ok(Packet::RemoveArena(arena)) => {
@@ -90,14 +289,57 @@ async fn handle_connection(
                     println!("[匹配池]({addr}) 正在删除匹配池 {arena}，此匹配池已不存在。")
                 }
             },
-            Ok(Packet::AddPlayer { arena, player, rank, length, init_rank_diff, speed }) => {
+            Ok(Packet::AddPlayer { arena, player, rank, length, init_rank_diff, speed, roles, latencies, avoid, rank_deviation, attributes }) => {
                 let try_arena = arenas.get(&arena);
                 if let Some(arena_) = try_arena {
-                    let rank_min = rank.saturating_sub(init_rank_diff);
-                    let rank_max = rank.saturating_add(init_rank_diff);
-                    arena_.1.insert(player.clone(), length as usize, rank_min as usize, rank_max as usize, speed as usize);
-                    senders.insert(player.clone(), addr);
-                    println!("[玩家匹配]({addr}) 成功向匹配池 {arena} 添加玩家 {player}（分数为 {rank}，初始区间为 {rank_min}至{rank_max}，数量为 {length}，扩散速度为 {speed}）");
+                    let (config, arena_, _strategy) = arena_.value();
+                    if is_pending(&pendings, &player) {
+                        println!("[玩家匹配]({addr}) 正在向 {arena} 添加玩家 {player}，但这位玩家有对局正在等待确认，添加失败！");
+                        return future::ok(());
+                    }
+                    let try_roles = member_roles(config, &roles, length);
+                    let Some(member_roles) = try_roles else {
+                        println!("[玩家匹配]({addr}) 正在向 {arena} 添加玩家 {player}，但角色列表 {roles:?} 和小队人数 {length} 不符或者含有不存在的角色，添加失败！");
+                        return future::ok(());
+                    };
+                    // 使用评分系统保存的评分，没有保存过的玩家使用初始评分
+                    let (rank, rank_deviation) = match config.rating {
+                        Some(system) if rank == 0.0 => {
+                            let try_rating = results.ratings.get(&arena).and_then(|store| store.rating(&player));
+                            let rating = try_rating.unwrap_or_else(|| system.initial());
+                            let deviation = if rank_deviation == 0.0 { rating.deviation } else { rank_deviation };
+                            (rating.rating, deviation)
+                        }
+                        _ => (rank, rank_deviation),
+                    };
+                    // 无穷大的扩散速度或者区间会在计算时变成NaN，所以每个小数都要检查
+                    let finite = [rank, init_rank_diff, speed, rank_deviation].iter().all(|value| value.is_finite());
+                    if !(finite && init_rank_diff >= 0.0 && speed >= 0.0 && rank_deviation >= 0.0) {
+                        println!("[玩家匹配]({addr}) 正在向 {arena} 添加玩家 {player}，但分数 {rank}、区间差值 {init_rank_diff}、扩散速度 {speed}、评分偏差 {rank_deviation} 超出范围或者是负数，添加失败！");
+                        return future::ok(());
+                    }
+                    let now = Instant::now();
+                    let info = Player {
+                        rank,
+                        init_rank_diff,
+                        deviation: rank_deviation,
+                        rank_diff: config.cap_rank_diff(init_rank_diff + rank_deviation),
+                        length: length as usize,
+                        speed,
+                        enqueued_at: now,
+                        expanding_since: now,
+                        roles: member_roles,
+                        latencies,
+                        attributes,
+                    };
+                    let (rank_min, rank_max) = (info.rank_min(), info.rank_max());
+                    arena_.insert(player.clone(), info);
+                    arena_.set_avoid(player.clone(), avoid);
+                    let mut queues = senders.entry(player.clone()).or_default();
+                    queues.retain(|(queued, _addr)| queued != &arena);
+                    queues.push((arena.clone(), addr));
+                    drop(queues);
+                    println!("[玩家匹配]({addr}) 成功向匹配池 {arena} 添加玩家 {player}（分数为 {rank}，评分偏差为 {rank_deviation}，初始区间为 {rank_min}至{rank_max}，数量为 {length}，扩散速度为每秒 {speed}）");
                 } else {
                     println!("[玩家匹配]({addr}) 正在向 {arena} 添加玩家 {player}（分数为 {rank}，数量为 {length}，区间差值为{init_rank_diff}），但此匹配池不存在。");
                 }
@@ -106,12 +348,119 @@ async fn handle_connection(
                 let try_arena = arenas.get(&arena);
                 if let Some(arena_) = try_arena {
                     arena_.1.remove(&player);
-                    senders.remove(&player);
+                    leave_queue(&senders, &player, &arena);
                     println!("[玩家匹配]({addr}) 成功从匹配池 {arena} 删除玩家 {player}。");
                 } else {
                     println!("[玩家匹配]({addr}) 正在向 {arena} 删除玩家 {player}，但此匹配池不存在。");
                 }
             },
+            Ok(Packet::UpdatePlayer { arena, player, rank, length, speed, roles }) => {
+                let try_arena = arenas.get(&arena);
+                // 修改失败的原因，修改成功时为None
+                let try_error = if let Some(arena_) = try_arena {
+                    let (config, arena_, _strategy) = arena_.value();
+                    let try_roles = member_roles(config, &roles, length);
+                    // 同时在其他匹配池排队的玩家匹配成功以后，或者正在等待确认时，已经不在排队表里了
+                    let queued_here = senders
+                        .get(&player)
+                        .is_some_and(|queues| queues.iter().any(|(queued, sender)| queued == &arena && sender == &addr));
+                    if is_pending(&pendings, &player) {
+                        Some(format!("玩家 {player} 有对局正在等待确认"))
+                    } else if !queued_here {
+                        Some(format!("玩家 {player} 不在匹配池 {arena} 中排队，或者不是这个大厅服务器添加的"))
+                    } else if !(rank.is_finite() && speed.is_finite() && speed >= 0.0) {
+                        Some(format!("分数 {rank} 或者扩散速度 {speed} 超出范围，或者扩散速度是负数"))
+                    } else if let Some(member_roles) = try_roles {
+                        // 使用评分系统时0表示保持原来的分数
+                        let keep_rank = config.rating.is_some() && rank == 0.0;
+                        let updated = arena_.update(&player, |info| {
+                            info.rebase_expansion(Instant::now());
+                            if !keep_rank {
+                                info.rank = rank;
+                            }
+                            info.length = length as usize;
+                            info.speed = speed;
+                            info.roles = member_roles;
+                        });
+                        if updated {
+                            println!("[玩家匹配]({addr}) 成功修改匹配池 {arena} 中的玩家 {player}（分数为 {rank}，数量为 {length}，扩散速度为每秒 {speed}），已经扩大的区间和等待时间保持不变。");
+                            None
+                        } else {
+                            Some(format!("玩家 {player} 不在匹配池 {arena} 中排队"))
+                        }
+                    } else {
+                        Some(format!("角色列表 {roles:?} 和小队人数 {length} 不符或者含有不存在的角色"))
+                    }
+                } else {
+                    Some(format!("匹配池 {arena} 不存在"))
+                };
+                if let Some(error_msg) = try_error {
+                    println!("[玩家匹配]({addr}) 修改匹配池 {arena} 中的玩家 {player} 失败：{error_msg}");
+                    let packet = Packet::UpdatePlayerFailure { arena, player, error_msg };
+                    let string = packet.to_string();
+                    let try_send = tx.unbounded_send(Message::Text(string));
+                    if let Err(e) = try_send {
+                        println!("[错误]({addr}) 内部错误：{e}");
+                    }
+                }
+            },
+            Ok(Packet::Backfill { arena, stage_request_id, rank, rank_diff, speed, open_slots, region, attributes }) => {
+                let try_arena = arenas.get(&arena);
+                if let Some(arena_) = try_arena {
+                    let (config, arena_, _strategy) = arena_.value();
+                    if open_slots.iter().all(|&slots| slots == 0) {
+                        arena_.remove_backfill(stage_request_id);
+                        println!("[补位]({addr}) 已取消房间 {stage_request_id} 在匹配池 {arena} 的补位请求。");
+                    } else if !config.roles.is_empty() {
+                        println!("[补位]({addr}) 房间 {stage_request_id} 请求在匹配池 {arena} 补位，但区分角色的匹配池不支持补位，请求失败！");
+                    } else if open_slots.len() > config.num_teams {
+                        println!("[补位]({addr}) 房间 {stage_request_id} 请求在匹配池 {arena} 补位，但空位列表 {open_slots:?} 比队伍数 {} 多，请求失败！", config.num_teams);
+                    } else if !([rank, rank_diff, speed].iter().all(|value| value.is_finite()) && rank_diff >= 0.0 && speed >= 0.0) {
+                        println!("[补位]({addr}) 房间 {stage_request_id} 请求在匹配池 {arena} 补位，但分数 {rank}、区间差值 {rank_diff}、扩散速度 {speed} 超出范围或者是负数，请求失败！");
+                    } else {
+                        let now = Instant::now();
+                        let anchor = Player {
+                            rank,
+                            init_rank_diff: rank_diff,
+                            deviation: 0.0,
+                            rank_diff: config.cap_rank_diff(rank_diff),
+                            length: 0,
+                            speed,
+                            enqueued_at: now,
+                            expanding_since: now,
+                            roles: Vec::new(),
+                            latencies: Vec::new(),
+                            attributes: Vec::new(),
+                        };
+                        let backfill = Backfill {
+                            anchor,
+                            open_slots: open_slots.iter().map(|&slots| slots as usize).collect(),
+                            region: if region.is_empty() { None } else { Some(region) },
+                            attributes,
+                            sender: addr,
+                        };
+                        println!("[补位]({addr}) 房间 {stage_request_id} 请求在匹配池 {arena} 补位：{backfill:?}");
+                        arena_.insert_backfill(stage_request_id, backfill);
+                    }
+                } else {
+                    println!("[补位]({addr}) 房间 {stage_request_id} 请求在匹配池 {arena} 补位，但此匹配池不存在。");
+                }
+            },
+            Ok(Packet::AcceptMatch { pending_id, player, accepted }) => {
+                let try_pending = pendings.get_mut(&pending_id);
+                match try_pending {
+                    Some(mut pending) if pending.formed.parties.iter().any(|(sender, party)| sender == &addr && party.player == player) => {
+                        if accepted != 0 {
+                            pending.accepted.insert(player.clone());
+                            println!("[确认]({addr}) 玩家 {player} 接受了对局 {pending_id}。");
+                        } else {
+                            pending.declined.insert(player.clone());
+                            println!("[确认]({addr}) 玩家 {player} 拒绝了对局 {pending_id}。");
+                        }
+                    },
+                    _ => println!("[确认]({addr}) 收到了玩家 {player} 对对局 {pending_id} 的回复，但这一局不存在、已经结束，或者这位玩家不是这个大厅服务器添加的。"),
+                }
+            },
             Ok(Packet::GetOrSubscribeState { period }) => {
                 let period = if period == 0 {
                     None
@@ -127,6 +476,37 @@ async fn handle_connection(
                     Err(e) => println!("内部错误：{e}"),
                 }
             },
+            Ok(Packet::MatchResult { stage_request_id, placements }) => {
+                // 先检查比赛结果再删除记录，格式不对时大厅服务器还可以重新报告
+                let try_record = results.records.get(&stage_request_id);
+                let try_arena = try_record.as_ref().and_then(|record| arenas.get(&record.arena));
+                if let (Some(record), Some(arena_)) = (try_record, try_arena) {
+                    let (config, _arena, _strategy) = arena_.value();
+                    let (record_arena, parties) = (record.arena.clone(), record.parties.clone());
+                    let from_lobby = record.lobbies.contains(&addr);
+                    drop(record);
+                    if !from_lobby {
+                        println!("[评分]({addr}) 房间 {stage_request_id} 没有这个大厅服务器添加的小队，已忽略它报告的比赛结果 {placements:?}。");
+                    } else if let Some(system) = &config.rating {
+                        // 不分队时每个小队单独排名，包括补位的小队
+                        let num_places = if config.num_teams == 1 { parties.len() } else { config.num_teams };
+                        if placements.len() == num_places {
+                            results.records.remove(&stage_request_id);
+                            let store = results.ratings.entry(record_arena.clone()).or_default();
+                            let ratings = store.update(system, &parties, &placements, config.num_teams);
+                            drop(store);
+                            println!("[评分]({addr}) 已按房间 {stage_request_id} 的比赛结果 {placements:?} 更新匹配池 {record_arena} 的评分：{ratings:?}");
+                        } else {
+                            println!("[评分]({addr}) 房间 {stage_request_id} 的比赛结果 {placements:?} 应当有 {num_places} 个名次，和匹配池 {record_arena} 不符，已忽略。");
+                        }
+                    } else {
+                        results.records.remove(&stage_request_id);
+                        println!("[评分]({addr}) 匹配池 {record_arena} 没有设置评分系统，已忽略房间 {stage_request_id} 的比赛结果。");
+                    }
+                } else {
+                    println!("[评分]({addr}) 收到了房间 {stage_request_id} 的比赛结果，但这个房间不存在、已经报告过结果或者匹配池已被删除。");
+                }
+            },
             Err(e) => {
                 println!("[错误]({addr}) 包格式错误：{}", e.0);
                 let packet = Packet::FormatError { error: e.0.to_string() };
@@ -153,20 +533,47 @@ async fn handle_connection(
     // 关闭排位反馈定时器
     dur_tx.close_channel();
 
-    // 移除此连接的玩家
+    // 移除此连接的玩家，只移除此连接添加的匹配池，玩家在其他大厅服务器添加的匹配池中继续排队
     let mut players = Vec::new();
-    for sender_ref in senders.iter() {
-        if sender_ref.value() == &addr {
-            let player = sender_ref.key();
-            players.push(player.clone());
+    for mut sender_ref in senders.iter_mut() {
+        let player = sender_ref.key().clone();
+        sender_ref
+            .value_mut()
+            .retain(|(arena, addr_for_this_player)| {
+                if &addr == addr_for_this_player {
+                    players.push((player.clone(), arena.clone()));
+                }
+                &addr != addr_for_this_player
+            });
+    }
+    senders.retain(|_player, queues| !queues.is_empty());
+    for arena_ref in arenas.iter() {
+        arena_ref.value().1.remove_backfills_of(&addr);
+    }
+    // 此连接还没有确认的小队当作拒绝
+    for mut pending in pendings.iter_mut() {
+        let pending = pending.value_mut();
+        for (sender, party) in &pending.formed.parties {
+            if sender == &addr && !pending.accepted.contains(&party.player) {
+                pending.declined.insert(party.player.clone());
+            }
+        }
+        // 等待确认的玩家已经从排队表取出，此连接在其他匹配池添加的排队也要删除，否则会留在那些匹配池里
+        for (player, queues) in pending.claimed.iter_mut() {
+            queues.retain(|(arena, addr_for_this_player)| {
+                let ghost = &addr == addr_for_this_player && arena != &pending.arena;
+                if ghost {
+                    players.push((player.clone(), arena.clone()));
+                }
+                !ghost
+            });
         }
     }
-    for player in players.iter() {
-        for arena_ref in arenas.iter() {
+    for (player, arena) in players.iter() {
+        if let Some(arena_ref) = arenas.get(arena) {
             arena_ref.value().1.remove(player);
         }
     }
-    senders.retain(|_player, addr_for_this_player| &addr != addr_for_this_player);
     println!(
         "[客户端]({}) 已移除该客户端注册的玩家，列表是：{:?}。",
         addr, players
@@ -176,6 +583,154 @@ async fn handle_connection(
     println!("[客户端]({}) 已经从排位匹配服务器解除注册，再见！", addr);
 }
 
+// 玩家不再在arena中排队，不在任何匹配池排队时删除这位玩家
+fn leave_queue(senders: &Senders, player: &str, arena: &str) {
+    if let Some(mut queues) = senders.get_mut(player) {
+        queues.retain(|(queued, _addr)| queued != arena);
+    }
+    senders.remove_if(player, |_player, queues| queues.is_empty());
+}
+
+// 玩家是否仍然在arena中排队
+fn is_queued(senders: &Senders, player: &str, arena: &str) -> bool {
+    senders
+        .get(player)
+        .is_some_and(|queues| queues.iter().any(|(queued, _addr)| queued == arena))
+}
+
+// 玩家是否有对局正在等待确认
+fn is_pending(pendings: &Pendings, player: &str) -> bool {
+    pendings.iter().any(|pending| {
+        let parties = &pending.value().formed.parties;
+        parties.iter().any(|(_addr, party)| party.player == player)
+    })
+}
+
+// 把一局的所有玩家从排队表中取出，这样其他匹配池不会再匹配到他们。
+// 返回和parties一一对应的、每位玩家排队的所有(匹配池, 大厅服务器地址)
+// 有玩家已经不在arena中排队时，放回已经取出的玩家并返回None
+fn claim(
+    senders: &Senders,
+    arena: &str,
+    parties: &[(String, Player)],
+) -> Option<Vec<Vec<(String, SocketAddr)>>> {
+    let mut claimed = Vec::with_capacity(parties.len());
+    for (player, _info) in parties {
+        let try_queues = senders.remove_if(player, |_player, queues| {
+            queues.iter().any(|(queued, _addr)| queued == arena)
+        });
+        let Some((_player, queues)) = try_queues else {
+            for ((player, _info), queues) in parties.iter().zip(claimed) {
+                let mut entry = senders.entry(player.clone()).or_default();
+                for (queued, addr) in queues {
+                    if !entry.iter().any(|(other, _addr)| other == &queued) {
+                        entry.push((queued, addr));
+                    }
+                }
+            }
+            return None;
+        };
+        claimed.push(queues);
+    }
+    Some(claimed)
+}
+
+// 记录在matched_arena匹配成功的玩家还在哪些其他匹配池排队，claimed是claim的返回值
+fn dequeue_elsewhere(
+    dequeued: &mut Vec<(String, String, SocketAddr, String)>,
+    matched_arena: &str,
+    ids: Vec<String>,
+    claimed: Vec<Vec<(String, SocketAddr)>>,
+) {
+    for (player, queues) in ids.into_iter().zip(claimed) {
+        for (other, addr) in queues {
+            if other != matched_arena {
+                dequeued.push((other, player.clone(), addr, matched_arena.to_string()));
+            }
+        }
+    }
+}
+
+// 创建房间：从匹配池删除这一局的小队，累计对局质量，然后请求中心服务器
+fn start_stage(
+    peers: &Peers,
+    results: &Results,
+    http_client: &reqwest::Client,
+    arena_name: &str,
+    arena: &Arena<String>,
+    config: &ArenaConfig,
+    formed: FormedMatch,
+) {
+    let ids = formed
+        .parties
+        .iter()
+        .map(|(_addr, party)| party.player.clone())
+        .collect::<Vec<_>>();
+    for player in &ids {
+        if let Some(info) = arena.remove(player) {
+            arena.record_wait(&info);
+        }
+    }
+    arena.record_match(&ids, config);
+    let stats = arena.record_quality(&formed.quality);
+    println!("[匹配池] {} 累计对局质量：{}", arena_name, stats);
+    tokio::spawn(request_http_and_send_id(
+        Arc::clone(peers),
+        Arc::clone(results),
+        arena_name.to_string(),
+        formed,
+        http_client.clone(),
+    ));
+}
+
+// 按添加小队的大厅服务器分组
+fn by_sender(parties: &[(SocketAddr, MatchedParty)]) -> HashMap<SocketAddr, Vec<MatchedParty>> {
+    let mut ans: HashMap<SocketAddr, Vec<MatchedParty>> = HashMap::new();
+    for (addr, party) in parties {
+        ans.entry(*addr).or_default().push(party.clone());
+    }
+    ans
+}
+
+// 发送给一个大厅服务器，大厅服务器已经断开时忽略
+fn send_packet(peers: &Peers, addr: &SocketAddr, packet: Packet) {
+    let string = packet.to_string();
+    let guard = lockfree_cuckoohash::pin();
+    if let Some(peer) = peers.get(addr, &guard) {
+        let try_send = peer.unbounded_send(Message::Text(string));
+        if let Err(e) = try_send {
+            println!("[匹配池] 内部错误：{e}");
+        }
+    }
+    drop(guard);
+}
+
+// 把AddPlayer中每位成员的角色名称转换成角色位掩码。匹配池不区分角色时忽略角色列表
+// 角色列表的长度和小队人数不符，或者含有匹配池中不存在的角色时返回None
+fn member_roles(config: &ArenaConfig, roles: &[Vec<String>], length: u64) -> Option<Vec<u64>> {
+    if config.roles.is_empty() || roles.is_empty() {
+        return Some(Vec::new());
+    }
+    if roles.len() as u64 != length {
+        return None;
+    }
+    roles
+        .iter()
+        .map(|member_roles| {
+            if member_roles.is_empty() {
+                return Some(role::ANY_ROLE);
+            }
+            member_roles.iter().try_fold(0, |mask, name| {
+                let index = config
+                    .roles
+                    .iter()
+                    .position(|(role, _count)| role == name)?;
+                Some(mask | 1 << index)
+            })
+        })
+        .collect()
+}
+
 async fn state_feedback_timer(
     peer: Tx,
     arenas: Arenas,
@@ -193,20 +748,36 @@ async fn state_feedback_timer(
             Err(_) => {}
         }
         if let Some(duration) = last_duration {
-            let player_info = DashMap::new();
+            // 同时在多个匹配池排队的玩家在每个匹配池各有一项
+            let mut player_info = Vec::new();
+            let mut arena_stats = Vec::new();
             for arena_ref in arenas.iter() {
-                let (_num_players, arena) = arena_ref.value();
-                let mut player_states = std::collections::HashMap::new();
-                arena.get_player_states(&mut player_states);
-                for (player, current_count) in player_states {
-                    player_info.insert(
-                        player.to_string(),
-                        (arena_ref.key().to_string(), current_count),
-                    );
+                let (_config, arena, _strategy) = arena_ref.value();
+                // 使用排位定时器计算好的状态，订阅的大厅服务器再多也不会重新计算
+                for (player, num_matched, wait_secs, eta_secs, position) in
+                    arena.player_states().iter()
+                {
+                    player_info.push(PlayerState {
+                        player: player.to_string(),
+                        arena: arena_ref.key().to_string(),
+                        num_matched: *num_matched,
+                        wait_secs: *wait_secs,
+                        eta_secs: *eta_secs,
+                        position: *position,
+                    });
                 }
+                let stats = arena.quality_stats();
+                arena_stats.push(ArenaStats {
+                    arena: arena_ref.key().to_string(),
+                    matches: stats.matches,
+                    quality: stats.averages(),
+                });
             }
-            println!("[状态反馈]({}) 玩家数量={}", addr, player_info.len());
-            let packet = Packet::ConnectionState { player_info };
+            println!("[状态反馈]({}) 排队数量={}", addr, player_info.len());
+            let packet = Packet::ConnectionState {
+                player_info,
+                arena_stats,
+            };
             let string = packet.to_string();
             let try_send = peer.unbounded_send(Message::Text(string));
             if let Err(e) = try_send {
@@ -221,118 +792,345 @@ async fn state_feedback_timer(
     println!("地址 {addr} 的排位状态反馈服务停止工作！");
 }
 
-async fn rank_timer(peers: Peers, arenas: Arenas, senders: Senders, http_client: reqwest::Client) {
+async fn rank_timer(
+    peers: Peers,
+    arenas: Arenas,
+    senders: Senders,
+    results: Results,
+    pendings: Pendings,
+    http_client: reqwest::Client,
+) {
     let mut interval = time::interval(time::Duration::from_secs(1));
     println!("排位定时器开始工作！");
     loop {
+        // (匹配池, 玩家, 大厅服务器地址, 匹配成功的匹配池)，同时在多个匹配池排队的玩家匹配成功以后，
+        // 遍历完所有匹配池再从其他匹配池删除
+        let mut dequeued = Vec::new();
         for arena_ref in arenas.iter() {
-            let (num_players, arena) = arena_ref.value();
-            let mut matched = Vec::new();
-            arena.rank_match(&mut matched);
-            let num_matched: usize = matched.iter().map(|(_name, length)| length).sum();
-            let (ans_matched, enough_but_impossible) = if num_matched > *num_players as usize {
-                // 玩家数量大于需要匹配的数量，运行动态规划的背包问题算法
-                // println!("数量过大！{}", num_matched);
-                let num_players = *num_players as usize;
-                let a = matched
-                    .clone()
+            let (config, arena, strategy) = arena_ref.value();
+            let conflicts = arena.conflicts(config);
+            // 已经在其他匹配池匹配成功的玩家不再参与匹配
+            let mut queued = arena.snapshot();
+            queued.retain(|(player, _info)| is_queued(&senders, player, arena_ref.key()));
+            // 补位优先于组成新的对局，等待时间长的房间先补
+            for (stage_request_id, backfill) in arena.backfills() {
+                let Some(group) = strategy::pick_backfill(config, &backfill, &queued, &conflicts)
+                else {
+                    continue;
+                };
+                let Some(claimed) = claim(&senders, arena_ref.key(), &group.parties) else {
+                    continue;
+                };
+                strategy::remove_grouped(&mut queued, &group);
+                arena.remove_backfill(stage_request_id);
+                println!(
+                    "[补位] 匹配池 {} 为房间 {stage_request_id} 补位了 {} 位玩家：{:?}，分队：{:?}",
+                    arena_ref.key(),
+                    group.parties.len(),
+                    group.parties,
+                    group.teams
+                );
+                // 请求补位的大厅服务器收到所有补位的小队，添加小队的大厅服务器收到各自的小队
+                let mut collected: HashMap<SocketAddr, Vec<MatchedParty>> = HashMap::new();
+                let mut players = Vec::with_capacity(group.parties.len());
+                for (i, ((player, info), &team)) in
+                    group.parties.iter().zip(&group.teams).enumerate()
+                {
+                    let item = MatchedParty {
+                        player: player.clone(),
+                        length: info.length as u64,
+                        team: team as u64,
+                        roles: Vec::new(),
+                    };
+                    let try_addr = claimed[i]
+                        .iter()
+                        .find(|(queued, _addr)| queued == arena_ref.key())
+                        .map(|(_queued, addr)| *addr);
+                    if let Some(addr) = try_addr.filter(|addr| addr != &backfill.sender) {
+                        collected.entry(addr).or_default().push(item.clone());
+                    }
+                    players.push(item);
+                }
+                collected.insert(backfill.sender, players);
+                // 补位的玩家也算这一局的玩家，收到比赛结果时一起更新评分
+                if let Some(mut record) = results.records.get_mut(&stage_request_id) {
+                    let parties = group.parties.iter().zip(&group.teams);
+                    record.parties.extend(
+                        parties.map(|((player, info), &team)| (player.clone(), info.length, team)),
+                    );
+                    record.lobbies.extend(collected.keys());
+                }
+                for (addr, players) in collected {
+                    let packet = Packet::BackfillSuccess {
+                        arena: arena_ref.key().clone(),
+                        stage_request_id,
+                        players,
+                    };
+                    send_packet(&peers, &addr, packet);
+                }
+                let ids = group
+                    .parties
                     .iter()
-                    .map(|(_name, length)| *length)
+                    .map(|(player, _info)| player.clone())
                     .collect::<Vec<_>>();
-                let mut dp = vec![vec![usize::MAX; num_players + 1]; matched.len()];
-                let mut l = [
-                    vec![Vec::new(); num_players + 1],
-                    vec![Vec::new(); num_players + 1],
-                ];
-                for j in 0..=num_players {
-                    dp[0][j] = usize::MAX;
-                }
-                for i in 0..matched.len() {
-                    for j in 0..=num_players {
-                        l[i % 2][j].clear();
-                    }
-                    if dp[i][a[i]] > 1 {
-                        dp[i][a[i]] = 1;
-                        l[i % 2][a[i]].push(i);
-                    }
-                    for j in 0..=num_players {
-                        if i >= 1 && j >= a[i] {
-                            if dp[i - 1][j - a[i]].saturating_add(1) < dp[i][j] {
-                                dp[i][j] = dp[i - 1][j - a[i]].saturating_add(1);
-                                let tmp = l[(i - 1) % 2][j - a[i]].clone();
-                                l[i % 2][j].extend(tmp);
-                                l[i % 2][j].push(i);
-                            }
-                        }
-                        if i >= 1 {
-                            if dp[i - 1][j] < dp[i][j] {
-                                dp[i][j] = dp[i - 1][j];
-                                let tmp = l[(i - 1) % 2][j].clone();
-                                l[i % 2][j].extend(tmp);
-                            }
-                        }
+                for player in &ids {
+                    if let Some(info) = arena.remove(player) {
+                        arena.record_wait(&info);
                     }
                 }
-                let enough_but_impossible = dp[matched.len() - 1][num_players] == usize::MAX;
-                let ans_list = &l[(matched.len() - 1) % 2][num_players];
-                let ret = matched
-                    .iter()
-                    .enumerate()
-                    .filter(|(idx, _)| ans_list.contains(idx))
-                    .map(|(_, player)| player.clone())
-                    .collect();
-                // println!("返回值 = {:?}", ret);
-                (ret, enough_but_impossible)
-            } else if num_matched == *num_players as usize {
-                // 刚好这么多玩家，不用分配了
-                // println!("数量刚好！{}", num_matched);
-                (matched.clone(), false)
-            } else {
-                // 玩家不够！返回空集
-                // println!("数量不够！{}", num_matched);
-                (Vec::new(), false)
-            };
-            if enough_but_impossible {
-                println!("[匹配池] {} 中应当匹配 {} 位玩家，但现有的小队无法匹配恰好这个玩家数的房间。这种情况比较罕见，服务器将在下一秒重试匹配算法。发生情况的玩家列表：{:?}",
-                arena_ref.key(), num_players, matched);
-                continue;
+                arena.record_match(&ids, config);
+                dequeue_elsewhere(&mut dequeued, arena_ref.key(), ids, claimed);
+            }
+            let mut rest = Vec::new();
+            if config.bots.is_some() {
+                rest = queued.clone();
+            }
+            let mut groups = strategy.form_groups(arena_ref.key(), config, queued, &conflicts);
+            // 真人凑不满一局的时候用机器人补满
+            for group in &groups {
+                strategy::remove_grouped(&mut rest, group);
+            }
+            while let Some(group) = strategy::pick_bot_group(config, &rest, &conflicts) {
+                strategy::remove_grouped(&mut rest, &group);
+                groups.push(group);
             }
-            let num_matched: usize = ans_matched.iter().map(|(_name, length)| length).sum();
-            if num_matched == *num_players as usize {
+            for Group {
+                parties,
+                teams,
+                roles,
+                region,
+                bots,
+                attributes,
+            } in groups
+            {
+                let Some(claimed) = claim(&senders, arena_ref.key(), &parties) else {
+                    println!(
+                        "[匹配池] {} 中有玩家在匹配过程中离开了队列，放弃这一局：{:?}",
+                        arena_ref.key(),
+                        parties
+                    );
+                    continue;
+                };
                 // 匹配成功
+                let quality = MatchQuality::of(&parties, &teams, config.num_teams);
                 println!(
-                    "[匹配池] {} 成功匹配了 {} 位玩家：{:?}",
+                    "[匹配池] {} 成功匹配了 {} 位玩家：{:?}，分队：{:?}，地区：{:?}，对局质量：{:?}，机器人：{:?}，属性：{:?}",
                     arena_ref.key(),
-                    ans_matched.len(),
-                    ans_matched
+                    parties.len(),
+                    parties,
+                    teams,
+                    region,
+                    quality,
+                    bots,
+                    attributes
                 );
-                let collected: DashMap<SocketAddr, Vec<(String, u64)>> = DashMap::new();
-                for (player, length) in ans_matched.clone() {
-                    let try_addr = senders.get(&player);
+                let mut matched = Vec::with_capacity(parties.len());
+                for (i, ((player, info), team)) in parties.iter().zip(teams).enumerate() {
+                    let try_addr = claimed[i]
+                        .iter()
+                        .find(|(queued, _addr)| queued == arena_ref.key())
+                        .map(|(_queued, addr)| addr);
                     if let Some(addr) = try_addr {
-                        collected
-                            .entry(addr.clone())
-                            .and_modify(|v| v.push((player.clone(), length as u64)))
-                            .or_insert_with(|| vec![(player.clone(), length as u64)]);
+                        let item = MatchedParty {
+                            player: player.clone(),
+                            length: info.length as u64,
+                            team: team as u64,
+                            roles: roles
+                                .get(i)
+                                .into_iter()
+                                .flatten()
+                                .map(|&role| config.roles[role].0.clone())
+                                .collect(),
+                        };
+                        matched.push((*addr, item));
                     }
                 }
-                tokio::spawn(request_http_and_send_id(
-                    Arc::clone(&peers),
-                    arena_ref.key().clone(),
-                    collected,
-                    http_client.clone(),
-                ));
-                let guard = lockfree_cuckoohash::pin();
-                for (player, _length) in &ans_matched {
-                    arena.remove(player);
+                let formed = FormedMatch {
+                    region,
+                    quality,
+                    parties: matched,
+                    bots,
+                    attributes,
+                };
+                let ids = parties
+                    .iter()
+                    .map(|(player, _info)| player.clone())
+                    .collect::<Vec<_>>();
+                match config.accept_timeout {
+                    None => {
+                        start_stage(
+                            &peers,
+                            &results,
+                            &http_client,
+                            arena_ref.key(),
+                            arena,
+                            config,
+                            formed,
+                        );
+                        dequeue_elsewhere(&mut dequeued, arena_ref.key(), ids, claimed);
+                    }
+                    Some(timeout) => {
+                        // 小队先留在匹配池里，它们已经从排队表取出，所以不会再被匹配，区间和等待时间照常计算。
+                        // 在其他匹配池的排队也先保留，开局时才删除
+                        let pending_id = NEXT_PENDING_ID.fetch_add(1, Ordering::Relaxed);
+                        for (addr, players) in by_sender(&formed.parties) {
+                            let packet = Packet::PendingMatch {
+                                arena: arena_ref.key().clone(),
+                                pending_id,
+                                players,
+                                timeout_secs: timeout.as_secs(),
+                            };
+                            send_packet(&peers, &addr, packet);
+                        }
+                        println!(
+                            "[确认] 匹配池 {} 的对局 {pending_id} 正在等待所有小队确认，最多等待 {} 秒。",
+                            arena_ref.key(),
+                            timeout.as_secs()
+                        );
+                        pendings.insert(
+                            pending_id,
+                            Pending {
+                                arena: arena_ref.key().clone(),
+                                formed,
+                                claimed: ids.into_iter().zip(claimed).collect(),
+                                accepted: HashSet::new(),
+                                declined: HashSet::new(),
+                                deadline: Instant::now() + timeout,
+                            },
+                        );
+                    }
                 }
-                drop(guard);
-                for (player, _length) in &ans_matched {
-                    senders.remove(player);
+            }
+            arena.rank_update(config);
+            arena.refresh_player_states();
+        }
+        // 等待确认的对局：所有小队都接受时创建房间，有小队拒绝或者超时的时候取消
+        let now = Instant::now();
+        let finished = pendings
+            .iter()
+            .filter(|entry| {
+                let pending = entry.value();
+                !pending.declined.is_empty()
+                    || pending.accepted.len() == pending.formed.parties.len()
+                    || now >= pending.deadline
+            })
+            .map(|entry| *entry.key())
+            .collect::<Vec<_>>();
+        for pending_id in finished {
+            let Some((_id, pending)) = pendings.remove(&pending_id) else {
+                continue;
+            };
+            // 复制一份匹配池，取消时还要查询其他匹配池，不能一直持有这个匹配池的锁
+            // 匹配池已被删除时所有小队都当作拒绝，和有小队拒绝时一样取消
+            let try_arena = arenas.get(&pending.arena).map(|arena_ref| {
+                let (config, arena, _strategy) = arena_ref.value();
+                (config.clone(), arena.clone())
+            });
+            // 确认期间离开匹配池的小队也当作拒绝
+            let ready = |player: &String| {
+                try_arena.as_ref().is_some_and(|(_config, arena)| {
+                    pending.accepted.contains(player) && arena.contains(player)
+                })
+            };
+            if let Some((config, arena)) = &try_arena {
+                if pending
+                    .formed
+                    .parties
+                    .iter()
+                    .all(|(_addr, party)| ready(&party.player))
+                {
+                    println!(
+                        "[确认] 匹配池 {} 的对局 {pending_id} 所有小队都已接受，开始创建房间。",
+                        pending.arena
+                    );
+                    start_stage(
+                        &peers,
+                        &results,
+                        &http_client,
+                        &pending.arena,
+                        arena,
+                        config,
+                        pending.formed,
+                    );
+                    let (ids, claimed) = pending.claimed.into_iter().unzip();
+                    dequeue_elsewhere(&mut dequeued, &pending.arena, ids, claimed);
+                    continue;
+                }
+            }
+            // 每个大厅服务器的(回到匹配池的小队, 删除的小队)
+            let mut cancelled: HashMap<SocketAddr, (Vec<String>, Vec<String>)> = HashMap::new();
+            let guard = lockfree_cuckoohash::pin();
+            for (addr, party) in &pending.formed.parties {
+                let (requeued, removed) = cancelled.entry(*addr).or_default();
+                // 接受了的小队放回排队表。它们一直留在匹配池里，所以区间和等待时间保持不变
+                let accepted = ready(&party.player) && peers.get(addr, &guard).is_some();
+                if accepted {
+                    requeued.push(party.player.clone());
+                } else {
+                    if let Some((_config, arena)) = &try_arena {
+                        arena.remove(&party.player);
+                    }
+                    removed.push(party.player.clone());
+                }
+                // 在其他匹配池的排队不受这一局影响，仍然在排队的都放回排队表
+                let queues = pending
+                    .claimed
+                    .iter()
+                    .filter(|(player, _queues)| player == &party.player)
+                    .flat_map(|(_player, queues)| queues);
+                for (queued, queued_addr) in queues {
+                    let still_queued = if queued == &pending.arena {
+                        accepted
+                    } else {
+                        peers.get(queued_addr, &guard).is_some()
+                            && arenas
+                                .get(queued)
+                                .is_some_and(|other| other.value().1.contains(&party.player))
+                    };
+                    if !still_queued {
+                        continue;
+                    }
+                    let mut entry = senders.entry(party.player.clone()).or_default();
+                    if !entry.iter().any(|(other, _addr)| other == queued) {
+                        entry.push((queued.clone(), *queued_addr));
+                    }
                 }
             }
-            arena.rank_update();
+            drop(guard);
+            let reason = if try_arena.is_some() {
+                "有小队拒绝或超时"
+            } else {
+                "匹配池已被删除"
+            };
+            println!(
+                "[确认] 匹配池 {} 的对局 {pending_id} {reason}，已取消：{cancelled:?}",
+                pending.arena
+            );
+            for (addr, (requeued, removed)) in cancelled {
+                let packet = Packet::MatchCancelled {
+                    arena: pending.arena.clone(),
+                    pending_id,
+                    requeued,
+                    removed,
+                };
+                send_packet(&peers, &addr, packet);
+            }
         }
+        for (arena, player, addr, matched_arena) in dequeued {
+            if let Some(arena_ref) = arenas.get(&arena) {
+                arena_ref.value().1.remove(&player);
+            }
+            println!("[匹配池] 玩家 {player} 已在匹配池 {matched_arena} 匹配成功，从匹配池 {arena} 中删除。");
+            let packet = Packet::Dequeued {
+                arena,
+                player,
+                matched_arena,
+            };
+            send_packet(&peers, &addr, packet);
+        }
+        results
+            .records
+            .retain(|_id, record| record.created_at.elapsed() < RESULT_TIMEOUT);
         interval.tick().await;
     }
 }
@@ -341,6 +1139,17 @@ async fn rank_timer(peers: Peers, arenas: Arenas, senders: Senders, http_client:
 struct CreateStageRequest {
     game: String,
     matching: String,
+    // 匹配池区分地区时，房间应当创建在这个地区
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+    // 这一局的质量指标
+    quality: MatchQuality,
+    // 需要游戏服务器创建AI的机器人
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bots: Vec<Bot>,
+    // 这一局取得一致的属性，例如地图和模式
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    attributes: HashMap<String, String>,
 }
 
 #[derive(serde::Deserialize)]
@@ -352,10 +1161,19 @@ enum CreateStageResponse {
 
 async fn request_http_and_send_id(
     peers: Peers,
+    results: Results,
     arena: String,
-    collected: DashMap<SocketAddr, Vec<(String, u64)>>,
+    formed: FormedMatch,
     http_client: reqwest::Client,
 ) {
+    let FormedMatch {
+        region,
+        quality,
+        parties,
+        bots,
+        attributes,
+    } = formed;
+    let collected = by_sender(&parties);
     let api_url = CONFIG
         .get::<String>("api.url")
         .unwrap_or("http://localhost:8081/customAddStage".to_string());
@@ -364,6 +1182,10 @@ async fn request_http_and_send_id(
         .json(&CreateStageRequest {
             game: arena.clone(),
             matching: format!("Rank#{}", rand::random::<u32>()),
+            region,
+            quality,
+            bots: bots.clone(),
+            attributes: attributes.iter().cloned().collect(),
         })
         .send()
         .await;
@@ -378,6 +1200,10 @@ async fn request_http_and_send_id(
                 println!("[匹配池] 中心服务器返回了错误！错误代码{error_id}，错误信息{error_msg}");
                 for item_collected in collected {
                     let (addr, players) = item_collected;
+                    let players = players
+                        .into_iter()
+                        .map(|party| (party.player, party.length))
+                        .collect();
                     let packet = Packet::MatchFailure {
                         arena: arena.clone(),
                         error_id,
@@ -400,6 +1226,10 @@ async fn request_http_and_send_id(
                 println!("[匹配池] 中心服务器返回的新增房间回复不是json格式！{e}");
                 for item_collected in collected {
                     let (addr, players) = item_collected;
+                    let players = players
+                        .into_iter()
+                        .map(|party| (party.player, party.length))
+                        .collect();
                     let packet = Packet::MatchFailure {
                         arena: arena.clone(),
                         error_id: 9000,
@@ -423,6 +1253,10 @@ async fn request_http_and_send_id(
             println!("[匹配池] 内部错误，无法连接到中心服务器！{e}");
             for item_collected in collected {
                 let (addr, players) = item_collected;
+                let players = players
+                    .into_iter()
+                    .map(|party| (party.player, party.length))
+                    .collect();
                 let packet = Packet::MatchFailure {
                     arena: arena.clone(),
                     error_id: 9001,
@@ -442,6 +1276,21 @@ async fn request_http_and_send_id(
             return;
         }
     };
+    // 记录这一局，收到比赛结果时更新评分
+    let lobbies = collected.keys().copied().collect();
+    let parties = parties
+        .into_iter()
+        .map(|(_addr, party)| (party.player, party.length as usize, party.team as usize))
+        .collect();
+    results.records.insert(
+        stage_request_id,
+        MatchRecord {
+            arena: arena.clone(),
+            parties,
+            lobbies,
+            created_at: Instant::now(),
+        },
+    );
     for item_collected in collected {
         let (addr, players) = item_collected;
         println!("[匹配池] 发送给地址 {addr} 的玩家列表：{:?}", players);
@@ -449,6 +1298,9 @@ async fn request_http_and_send_id(
             arena: arena.clone(),
             stage_request_id,
             players,
+            quality,
+            bots: bots.clone(),
+            attributes: attributes.clone(),
         };
         let string = packet.to_string();
         let guard = lockfree_cuckoohash::pin();
@@ -469,6 +1321,8 @@ async fn main() {
     let peers = Arc::new(LockFreeCuckooHash::new());
     let arenas = Arc::new(DashMap::new());
     let senders = Arc::new(DashMap::new());
+    let results = Arc::new(MatchResults::default());
+    let pendings = Arc::new(DashMap::new());
 
     let websocket_addr = CONFIG
         .get::<String>("websocket.addr")
@@ -492,6 +1346,8 @@ async fn main() {
         Arc::clone(&peers),
         Arc::clone(&arenas),
         Arc::clone(&senders),
+        Arc::clone(&results),
+        Arc::clone(&pendings),
         http_client.clone(),
     ));
 
@@ -501,6 +1357,8 @@ async fn main() {
             Arc::clone(&peers),
             Arc::clone(&arenas),
             Arc::clone(&senders),
+            Arc::clone(&results),
+            Arc::clone(&pendings),
             stream,
             addr,
         ));
@@ -1,6 +1,7 @@
-use std::{collections::VecDeque, str::FromStr};
+use std::{collections::VecDeque, fmt, str::FromStr};
 
-use dashmap::DashMap;
+use crate::quality::MatchQuality;
+use crate::strategy::Bot;
 
 // 包，可以是收的也可以是发的
 #[derive(Debug)]
@@ -8,19 +9,82 @@ pub enum Packet {
     AddArena {
         arena: String,
         num_players: u64,
+        // 一局分成几个队伍，每个队伍人数相同。0和1都表示不分队
+        num_teams: u64,
+        // 非0表示互相接受模式：每位玩家的区间都必须包含同一局其他玩家的分数
+        mutual: u64,
+        // 区间扩散方式。0 => 线性，每秒增加speed；1 => 指数，每秒乘以expansion_ratio；2 => 按expansion_steps查表
+        expansion: u64,
+        // 指数扩散的倍率，千分比，例如1100表示每秒乘以1.1
+        expansion_ratio: u64,
+        // (等待秒数, 区间半径)，等待超过这个秒数后区间半径至少是这个值。半径为0表示不再限制分数
+        expansion_steps: Vec<(u64, f64)>,
+        // 区间半径的上限，0表示不限制
+        max_rank_diff: f64,
+        // 每局最少的玩家数，0表示和num_players相同。此时num_players是每局最多的玩家数
+        min_players: u64,
+        // 有玩家等待超过这么多秒以后，达到min_players就开始，否则等到num_players
+        fill_after_secs: u64,
+        // 可选的小队比一局需要的多时优先选择哪些。0 => 等待时间长的，1 => 分数跨度小的，
+        // 2 => 平局概率大的，需要设置draw_beta
+        priority: u64,
+        // 匹配策略的名称。空字符串或"rank" => 按分数排位，"fifo" => 不看分数，按先后顺序
+        strategy: String,
+        // 每个队伍的角色位置，(角色名称, 位置数量)。为空表示不区分角色
+        roles: Vec<(String, u64)>,
+        // 进入匹配池时的地区延迟阈值，毫秒。0表示不区分地区
+        latency_threshold: u64,
+        // 地区延迟阈值每秒放宽多少毫秒
+        latency_speed: u64,
+        // 地区延迟阈值的上限，0表示不限制
+        max_latency_threshold: u64,
+        // 一起匹配过的玩家在这么多秒内不会再分到同一局，0表示不限制
+        recent_cooldown_secs: u64,
+        // 评分系统。0 => 不使用，1 => Elo，2 => Glicko-2
+        rating_system: u64,
+        // Elo每局评分变化的最大值，0表示使用默认值32。Glicko-2时为tau的千分比，0表示使用默认值500
+        rating_param: u64,
+        // 预测平局概率时表现的随机波动，单位和分数相同。0表示不按平局概率判断
+        draw_beta: f64,
+        // 一局的平局概率至少是多少，千分比
+        min_draw_quality: u64,
+        // 等待最久的小队等待了这么多秒以后，平局概率的要求降低到0。0表示不降低
+        draw_relax_secs: u64,
+        // 匹配成功以后等待所有小队确认的秒数，超时当作拒绝。0表示不需要确认，直接创建房间
+        accept_timeout_secs: u64,
+        // 等待最久的小队等待了这么多秒以后，真人凑不满一局时用机器人补满。0表示不使用机器人
+        bot_fill_after_secs: u64,
+        // 使用机器人时一局至少要有几位真人
+        bot_min_players: u64,
+        // (属性名称, 要求, 放宽秒数)，一局的所有小队必须在这些属性上取得一致。
+        // 要求：0 => 第一个取值必须相同, 1 => 至少有一个共同的取值, 2 => 和1相同，但小队等待超过放宽秒数以后不再要求
+        attributes: Vec<(String, u64, u64)>,
     },
     RemoveArena(String),
     AddPlayer {
         arena: String,
         player: String,
-        rank: u64,
+        // 可以是负数或小数。匹配池设置了评分系统时，0表示使用评分系统保存的评分
+        rank: f64,
         // 通常是1。用于按队伍为单位匹配时，以队长的名义和分数匹配，此时length为队伍成员的数量
         length: u64,
         // 初始区间配置，用于加快匹配速度，初始区间为[rank - diff, rank + diff]。
         // 通常diff为0
-        init_rank_diff: u64,
-        // 匹配速度。如果init_rank_diff为0且speed为0，这个玩家可能永远无法匹配成功
-        speed: u64,
+        init_rank_diff: f64,
+        // 匹配速度，区间每秒向两边各扩散多少分。如果init_rank_diff为0且speed为0，这个玩家可能永远无法匹配成功
+        speed: f64,
+        // 小队每位成员可以接受的角色名称，长度等于length。为空表示所有成员都可以接受任何角色，
+        // 某位成员的列表为空表示这位成员可以接受任何角色
+        roles: Vec<Vec<String>>,
+        // (地区名称, 延迟毫秒数)，小队通常使用成员中最高的延迟。为空表示可以在任何地区开局
+        latencies: Vec<(String, u64)>,
+        // 不想分到同一局的玩家名称，例如屏蔽的玩家。对方也不会和这个玩家分到同一局
+        avoid: Vec<String>,
+        // 评分偏差，分数越不确定越大。初始区间会加宽这么多，按平局概率判断时也会用到。
+        // 匹配池设置了评分系统并且rank为0时，0表示使用评分系统保存的评分偏差
+        rank_deviation: f64,
+        // (属性名称, 可以接受的取值)，例如地图、模式、语言，靠前的取值优先。没有发送的属性表示可以接受任何取值
+        attributes: Vec<(String, Vec<String>)>,
     },
     RemovePlayer {
         arena: String,
@@ -31,15 +95,21 @@ pub enum Packet {
         period: u64,
     },
     ConnectionState {
-        // 玩家名称 => (匹配池名称, 已经匹配的人数)
-        player_info: DashMap<String, (String, u64)>,
+        // 每位玩家在每个排队的匹配池的状态，同时在多个匹配池排队的玩家有多项
+        player_info: Vec<PlayerState>,
+        // 每个匹配池累计的对局质量
+        arena_stats: Vec<ArenaStats>,
     },
     MatchSuccess {
         arena: String,
         stage_request_id: u64, // 请求创建房间的requestId，然后交给各个nk去轮询检查房间是否创建成功
-        // String是玩家的名称，u64是队伍内玩家的个数。u64通常是1
-        // 若不为1表示String为队长的名字
-        players: Vec<(String, u64)>,
+        players: Vec<MatchedParty>,
+        // 这一局的质量指标
+        quality: MatchQuality,
+        // 补满这一局的机器人，发送给这一局的所有大厅服务器
+        bots: Vec<Bot>,
+        // 这一局取得一致的属性，(属性名称, 取值)
+        attributes: Vec<(String, String)>,
     },
     MatchFailure {
         arena: String,
@@ -50,6 +120,129 @@ pub enum Packet {
     FormatError {
         error: String,
     },
+    // 大厅服务器报告一局的比赛结果，匹配池设置了评分系统时会更新这一局所有小队的评分
+    MatchResult {
+        // MatchSuccess中的stage_request_id
+        stage_request_id: u64,
+        // 每个队伍的名次，下标是队伍编号。0表示第一名，名次相同表示平局。
+        // 不分队的匹配池是每个小队的名次，按MatchSuccess中的顺序，补位的小队按BackfillSuccess的顺序排在后面
+        placements: Vec<u64>,
+    },
+    // 玩家同时在多个匹配池排队，在其中一个匹配池匹配成功以后，发送给在其他匹配池添加这位玩家的大厅服务器
+    Dequeued {
+        // 玩家被移出的匹配池
+        arena: String,
+        player: String,
+        // 玩家匹配成功的匹配池
+        matched_arena: String,
+    },
+    // 已经开始的房间有玩家离开时，请求在匹配池中找玩家补位。补位优先于组成新的对局
+    // 同一个房间再次发送时替换原来的请求
+    Backfill {
+        arena: String,
+        // MatchSuccess中的stage_request_id
+        stage_request_id: u64,
+        // 房间的分数，通常是留下的玩家的平均分
+        rank: f64,
+        // 可以接受的分数区间为[rank - rank_diff, rank + rank_diff]，和玩家的区间一样按匹配池的扩散方式扩大
+        rank_diff: f64,
+        // 区间每秒向两边各扩散多少分
+        speed: f64,
+        // 每个队伍还缺几个人，下标是队伍编号。全为0或为空表示取消补位请求
+        open_slots: Vec<u64>,
+        // 房间所在的地区，匹配池区分地区时补位的小队在这个地区的延迟不能超过阈值。为空表示不限制
+        region: String,
+        // 房间的属性，(属性名称, 取值)，通常是MatchSuccess中取得一致的属性。补位的小队必须能接受这些属性，
+        // 匹配池规定了但房间没有发送的属性只接受不限制这个属性的小队
+        attributes: Vec<(String, String)>,
+    },
+    // 补位成功，发送给请求补位的大厅服务器
+    BackfillSuccess {
+        arena: String,
+        stage_request_id: u64,
+        players: Vec<MatchedParty>,
+    },
+    // 匹配池需要确认时，匹配成功以后先发送这个包，每个大厅服务器收到自己的小队。所有小队都接受以后才创建房间
+    PendingMatch {
+        arena: String,
+        pending_id: u64,
+        players: Vec<MatchedParty>,
+        // 这么多秒内没有回复的小队当作拒绝
+        timeout_secs: u64,
+    },
+    // 大厅服务器回复一个小队是否接受这一局
+    AcceptMatch {
+        pending_id: u64,
+        player: String,
+        // 非0表示接受
+        accepted: u64,
+    },
+    // 有小队拒绝或者超时，这一局取消
+    MatchCancelled {
+        arena: String,
+        pending_id: u64,
+        // 接受了的小队，已经回到匹配池，区间和等待时间保持不变
+        requeued: Vec<String>,
+        // 拒绝、超时或者已经离开的小队，已经从匹配池删除
+        removed: Vec<String>,
+    },
+    // 修改排队中的玩家，保留已经扩大的区间和进入匹配池的时间。例如小队有成员加入或离开
+    UpdatePlayer {
+        arena: String,
+        player: String,
+        // 新的分数。匹配池设置了评分系统时，0表示保持原来的分数
+        rank: f64,
+        // 新的小队人数
+        length: u64,
+        // 新的扩散速度，只影响以后的扩散
+        speed: f64,
+        // 小队每位成员可以接受的角色名称，和AddPlayer中的相同
+        roles: Vec<Vec<String>>,
+    },
+    // 修改玩家失败，例如玩家不在这个匹配池排队，发送给请求修改的大厅服务器
+    UpdatePlayerFailure {
+        arena: String,
+        player: String,
+        error_msg: String,
+    },
+}
+
+// 匹配成功的一个小队
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchedParty {
+    // 玩家的名称。小队人数不为1时是队长的名字
+    pub player: String,
+    // 小队内玩家的个数，通常是1
+    pub length: u64,
+    // 这个小队被分到的队伍编号，从0开始
+    pub team: u64,
+    // 每位成员分到的角色名称，顺序和AddPlayer中的相同。匹配池不区分角色时为空
+    pub roles: Vec<String>,
+}
+
+// ConnectionState中一位玩家在一个匹配池的状态
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerState {
+    pub player: String,
+    pub arena: String,
+    // 区间内同时匹配的最多人数
+    pub num_matched: u64,
+    // 已经等待的秒数
+    pub wait_secs: u64,
+    // 估计还要等待的秒数，0表示这个匹配池的记录还不够，无法估计
+    pub eta_secs: u64,
+    // 按进入匹配池的先后排在第几位，从1开始
+    pub position: u64,
+}
+
+// ConnectionState中一个匹配池累计的对局质量
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArenaStats {
+    pub arena: String,
+    // 匹配成功的局数
+    pub matches: u64,
+    // 各项指标在所有对局中的平均值，max_wait_secs是所有对局中等待最久的秒数
+    pub quality: MatchQuality,
 }
 
 // 包格式错误
@@ -66,15 +259,15 @@ impl FromStr for Packet {
     }
 }
 
-impl ToString for Packet {
-    fn to_string(&self) -> String {
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut writer = CharWriter {
             inner: VecDeque::new(),
         };
         writer.write_packet(self);
         let mut ans = String::new();
         ans.extend(writer.inner);
-        ans
+        f.write_str(&ans)
     }
 }
 
@@ -87,16 +280,80 @@ impl CharWriter {
     fn write_packet(&mut self, packet: &Packet) {
         self.inner.push_back('1'); // version
         match packet {
-            Packet::AddArena { arena, num_players } => {
+            Packet::AddArena {
+                arena,
+                num_players,
+                num_teams,
+                mutual,
+                expansion,
+                expansion_ratio,
+                expansion_steps,
+                max_rank_diff,
+                min_players,
+                fill_after_secs,
+                priority,
+                strategy,
+                roles,
+                latency_threshold,
+                latency_speed,
+                max_latency_threshold,
+                recent_cooldown_secs,
+                rating_system,
+                rating_param,
+                draw_beta,
+                min_draw_quality,
+                draw_relax_secs,
+                accept_timeout_secs,
+                bot_fill_after_secs,
+                bot_min_players,
+                attributes,
+            } => {
                 self.inner.push_back(',');
                 self.inner.push_back('1');
-                self.write_string(&arena);
+                self.write_string(arena);
                 self.write_number(*num_players);
+                self.write_number(*num_teams);
+                self.write_number(*mutual);
+                self.write_number(*expansion);
+                self.write_number(*expansion_ratio);
+                self.write_number(expansion_steps.len() as u64);
+                for (secs, diff) in expansion_steps {
+                    self.write_number(*secs);
+                    self.write_decimal(*diff);
+                }
+                self.write_decimal(*max_rank_diff);
+                self.write_number(*min_players);
+                self.write_number(*fill_after_secs);
+                self.write_number(*priority);
+                self.write_string(strategy);
+                self.write_number(roles.len() as u64);
+                for (role, count) in roles {
+                    self.write_string(role);
+                    self.write_number(*count);
+                }
+                self.write_number(*latency_threshold);
+                self.write_number(*latency_speed);
+                self.write_number(*max_latency_threshold);
+                self.write_number(*recent_cooldown_secs);
+                self.write_number(*rating_system);
+                self.write_number(*rating_param);
+                self.write_decimal(*draw_beta);
+                self.write_number(*min_draw_quality);
+                self.write_number(*draw_relax_secs);
+                self.write_number(*accept_timeout_secs);
+                self.write_number(*bot_fill_after_secs);
+                self.write_number(*bot_min_players);
+                self.write_number(attributes.len() as u64);
+                for (key, kind, relax_secs) in attributes {
+                    self.write_string(key);
+                    self.write_number(*kind);
+                    self.write_number(*relax_secs);
+                }
             }
             Packet::RemoveArena(arena) => {
                 self.inner.push_back(',');
                 self.inner.push_back('2');
-                self.write_string(&arena);
+                self.write_string(arena);
             }
             Packet::AddPlayer {
                 arena,
@@ -105,52 +362,106 @@ impl CharWriter {
                 length,
                 init_rank_diff,
                 speed,
+                roles,
+                latencies,
+                avoid,
+                rank_deviation,
+                attributes,
             } => {
                 self.inner.push_back(',');
                 self.inner.push_back('3');
-                self.write_string(&arena);
-                self.write_string(&player);
-                self.write_number(*rank);
+                self.write_string(arena);
+                self.write_string(player);
+                self.write_decimal(*rank);
                 self.write_number(*length);
-                self.write_number(*init_rank_diff);
-                self.write_number(*speed);
+                self.write_decimal(*init_rank_diff);
+                self.write_decimal(*speed);
+                self.write_number(roles.len() as u64);
+                for member_roles in roles {
+                    self.write_number(member_roles.len() as u64);
+                    for role in member_roles {
+                        self.write_string(role);
+                    }
+                }
+                self.write_number(latencies.len() as u64);
+                for (region, latency) in latencies {
+                    self.write_string(region);
+                    self.write_number(*latency);
+                }
+                self.write_number(avoid.len() as u64);
+                for other in avoid {
+                    self.write_string(other);
+                }
+                self.write_decimal(*rank_deviation);
+                self.write_number(attributes.len() as u64);
+                for (key, values) in attributes {
+                    self.write_string(key);
+                    self.write_number(values.len() as u64);
+                    for value in values {
+                        self.write_string(value);
+                    }
+                }
             }
             Packet::RemovePlayer { arena, player } => {
                 self.inner.push_back(',');
                 self.inner.push_back('4');
-                self.write_string(&arena);
-                self.write_string(&player);
+                self.write_string(arena);
+                self.write_string(player);
             }
             Packet::GetOrSubscribeState { period } => {
                 self.inner.push_back(',');
                 self.inner.push_back('5');
                 self.write_number(*period);
             }
-            Packet::ConnectionState { player_info } => {
+            Packet::ConnectionState {
+                player_info,
+                arena_stats,
+            } => {
                 self.inner.push_back(',');
                 self.inner.push_back('6');
+                // 列表的格式和最初的版本相同，后来加的字段按相同的顺序写在列表后面，旧版本的客户端可以忽略
                 self.write_number(player_info.len() as u64);
-                for info in player_info {
-                    let player = info.key();
-                    let (arena, num_matched) = info.value();
-                    self.write_string(player);
-                    self.write_string(arena);
-                    self.write_number(*num_matched);
+                for state in player_info {
+                    self.write_string(&state.player);
+                    self.write_string(&state.arena);
+                    self.write_number(state.num_matched);
+                }
+                self.write_number(player_info.len() as u64);
+                for state in player_info {
+                    self.write_number(state.wait_secs);
+                    self.write_number(state.eta_secs);
+                    self.write_number(state.position);
+                }
+                self.write_number(arena_stats.len() as u64);
+                for stats in arena_stats {
+                    self.write_string(&stats.arena);
+                    self.write_number(stats.matches);
+                    self.write_quality(&stats.quality);
                 }
             }
             Packet::MatchSuccess {
                 arena,
                 stage_request_id,
                 players,
+                quality,
+                bots,
+                attributes,
             } => {
                 self.inner.push_back(',');
                 self.inner.push_back('7');
-                self.write_string(&arena);
+                self.write_string(arena);
                 self.write_number(*stage_request_id);
-                self.write_number(players.len() as u64);
-                for (player, length) in players {
-                    self.write_string(&player);
-                    self.write_number(*length)
+                self.write_matched_parties(players);
+                self.write_quality(quality);
+                self.write_number(bots.len() as u64);
+                for bot in bots {
+                    self.write_number(bot.team as u64);
+                    self.write_decimal(bot.rank);
+                }
+                self.write_number(attributes.len() as u64);
+                for (key, value) in attributes {
+                    self.write_string(key);
+                    self.write_string(value);
                 }
             }
             Packet::MatchFailure {
@@ -161,19 +472,174 @@ impl CharWriter {
             } => {
                 self.inner.push_back(',');
                 self.inner.push_back('8');
-                self.write_string(&arena);
+                self.write_string(arena);
                 self.write_number(*error_id);
-                self.write_string(&error_msg);
+                self.write_string(error_msg);
                 self.write_number(players.len() as u64);
                 for (player, length) in players {
-                    self.write_string(&player);
+                    self.write_string(player);
                     self.write_number(*length)
                 }
             }
             Packet::FormatError { error } => {
                 self.inner.push_back(',');
                 self.inner.push_back('9');
-                self.write_string(&error);
+                self.write_string(error);
+            }
+            Packet::MatchResult {
+                stage_request_id,
+                placements,
+            } => {
+                self.write_number(10);
+                self.write_number(*stage_request_id);
+                self.write_number(placements.len() as u64);
+                for placement in placements {
+                    self.write_number(*placement);
+                }
+            }
+            Packet::Dequeued {
+                arena,
+                player,
+                matched_arena,
+            } => {
+                self.write_number(11);
+                self.write_string(arena);
+                self.write_string(player);
+                self.write_string(matched_arena);
+            }
+            Packet::Backfill {
+                arena,
+                stage_request_id,
+                rank,
+                rank_diff,
+                speed,
+                open_slots,
+                region,
+                attributes,
+            } => {
+                self.write_number(12);
+                self.write_string(arena);
+                self.write_number(*stage_request_id);
+                self.write_decimal(*rank);
+                self.write_decimal(*rank_diff);
+                self.write_decimal(*speed);
+                self.write_number(open_slots.len() as u64);
+                for slots in open_slots {
+                    self.write_number(*slots);
+                }
+                self.write_string(region);
+                self.write_number(attributes.len() as u64);
+                for (key, value) in attributes {
+                    self.write_string(key);
+                    self.write_string(value);
+                }
+            }
+            Packet::BackfillSuccess {
+                arena,
+                stage_request_id,
+                players,
+            } => {
+                self.write_number(13);
+                self.write_string(arena);
+                self.write_number(*stage_request_id);
+                self.write_matched_parties(players);
+            }
+            Packet::PendingMatch {
+                arena,
+                pending_id,
+                players,
+                timeout_secs,
+            } => {
+                self.write_number(14);
+                self.write_string(arena);
+                self.write_number(*pending_id);
+                self.write_matched_parties(players);
+                self.write_number(*timeout_secs);
+            }
+            Packet::AcceptMatch {
+                pending_id,
+                player,
+                accepted,
+            } => {
+                self.write_number(15);
+                self.write_number(*pending_id);
+                self.write_string(player);
+                self.write_number(*accepted);
+            }
+            Packet::MatchCancelled {
+                arena,
+                pending_id,
+                requeued,
+                removed,
+            } => {
+                self.write_number(16);
+                self.write_string(arena);
+                self.write_number(*pending_id);
+                self.write_number(requeued.len() as u64);
+                for player in requeued {
+                    self.write_string(player);
+                }
+                self.write_number(removed.len() as u64);
+                for player in removed {
+                    self.write_string(player);
+                }
+            }
+            Packet::UpdatePlayer {
+                arena,
+                player,
+                rank,
+                length,
+                speed,
+                roles,
+            } => {
+                self.write_number(17);
+                self.write_string(arena);
+                self.write_string(player);
+                self.write_decimal(*rank);
+                self.write_number(*length);
+                self.write_decimal(*speed);
+                self.write_number(roles.len() as u64);
+                for member_roles in roles {
+                    self.write_number(member_roles.len() as u64);
+                    for role in member_roles {
+                        self.write_string(role);
+                    }
+                }
+            }
+            Packet::UpdatePlayerFailure {
+                arena,
+                player,
+                error_msg,
+            } => {
+                self.write_number(18);
+                self.write_string(arena);
+                self.write_string(player);
+                self.write_string(error_msg);
+            }
+        }
+    }
+    #[inline]
+    fn write_quality(&mut self, quality: &MatchQuality) {
+        self.write_number(quality.rank_spread);
+        self.write_number(quality.rank_std_dev);
+        self.write_number(quality.team_imbalance);
+        self.write_number(quality.max_wait_secs);
+        self.write_number(quality.avg_wait_secs);
+    }
+    #[inline]
+    fn write_matched_parties(&mut self, players: &[MatchedParty]) {
+        // 列表的格式和最初的MatchSuccess相同，队伍编号和角色是后来加的，按相同的顺序写在列表后面
+        self.write_number(players.len() as u64);
+        for party in players {
+            self.write_string(&party.player);
+            self.write_number(party.length);
+        }
+        self.write_number(players.len() as u64);
+        for party in players {
+            self.write_number(party.team);
+            self.write_number(party.roles.len() as u64);
+            for role in &party.roles {
+                self.write_string(role);
             }
         }
     }
@@ -184,6 +650,13 @@ impl CharWriter {
         self.inner
             .extend(string.chars().collect::<VecDeque<char>>());
     }
+    // 分数等可以是负数或小数的字段，整数时和write_number的格式相同
+    #[inline]
+    fn write_decimal(&mut self, number: f64) {
+        let string = format!(",{}", number);
+        self.inner
+            .extend(string.chars().collect::<VecDeque<char>>());
+    }
     #[inline]
     fn write_string(&mut self, string: &str) {
         self.write_number(string.len() as u64);
@@ -207,24 +680,34 @@ impl CharReader {
     }
     #[inline]
     fn read_v1(&mut self) -> Result<Packet, PacketFormat> {
-        match (self.inner.pop_front(), self.inner.pop_front()) {
-            (Some('1'), Some(',')) => self.read_v1_add_arena(),
-            (Some('2'), Some(',')) => self.read_v1_remove_arena(),
-            (Some('3'), Some(',')) => self.read_v1_add_player(),
-            (Some('4'), Some(',')) => self.read_v1_remove_player(),
-            (Some('5'), Some(',')) => self.read_v1_get_or_subscribe_state(),
-            (Some('6'), Some(',')) => self.read_v1_connection_state(),
-            (Some('7'), Some(',')) => self.read_v1_match_success(),
-            (Some('8'), Some(',')) => self.read_v1_match_failure(),
-            (Some('9'), Some(',')) => self.read_v1_format_error(),
-            _ => Err(PacketFormat("不支持除了1-9之外的包类别。")),
+        // 包类别可能不止一位数字，read_number会同时读掉后面的逗号
+        match self.read_number() {
+            1 => self.read_v1_add_arena(),
+            2 => self.read_v1_remove_arena(),
+            3 => self.read_v1_add_player(),
+            4 => self.read_v1_remove_player(),
+            5 => self.read_v1_get_or_subscribe_state(),
+            6 => self.read_v1_connection_state(),
+            7 => self.read_v1_match_success(),
+            8 => self.read_v1_match_failure(),
+            9 => self.read_v1_format_error(),
+            10 => self.read_v1_match_result(),
+            11 => self.read_v1_dequeued(),
+            12 => self.read_v1_backfill(),
+            13 => self.read_v1_backfill_success(),
+            14 => self.read_v1_pending_match(),
+            15 => self.read_v1_accept_match(),
+            16 => self.read_v1_match_cancelled(),
+            17 => self.read_v1_update_player(),
+            18 => self.read_v1_update_player_failure(),
+            _ => Err(PacketFormat("不支持除了1-18之外的包类别。")),
         }
     }
     #[inline]
     fn read_number(&mut self) -> u64 {
         let mut cur = self.inner.pop_front();
         while let Some(c) = cur {
-            if c.to_digit(10).is_some() {
+            if c.is_ascii_digit() {
                 break;
             }
             cur = self.inner.pop_front();
@@ -239,7 +722,34 @@ impl CharReader {
                 return ans;
             }
         }
-        return ans;
+        ans
+    }
+    // 读取带符号的小数，例如-12.5。旧版本客户端发送的整数也可以读取，格式错误时返回0，位数太多超出f64范围时报错
+    #[inline]
+    fn read_decimal(&mut self) -> Result<f64, PacketFormat> {
+        let mut cur = self.inner.pop_front();
+        while let Some(c) = cur {
+            if c.is_ascii_digit() || c == '-' || c == '.' {
+                break;
+            }
+            cur = self.inner.pop_front();
+        }
+        let mut text = String::new();
+        while let Some(c) = cur {
+            if c.is_ascii_digit() || c == '.' || (c == '-' && text.is_empty()) {
+                text.push(c);
+                cur = self.inner.pop_front();
+            } else {
+                break;
+            }
+        }
+        // 加0.0把-0变成0，这样同一个分数只有一种表示
+        let number = text.parse::<f64>().map_or(0.0, |number| number + 0.0);
+        if number.is_finite() {
+            Ok(number)
+        } else {
+            Err(PacketFormat("小数超出范围。"))
+        }
     }
     #[inline]
     fn read_string(&mut self) -> String {
@@ -256,7 +766,78 @@ impl CharReader {
     fn read_v1_add_arena(&mut self) -> Result<Packet, PacketFormat> {
         let arena = self.read_string();
         let num_players = self.read_number();
-        Ok(Packet::AddArena { arena, num_players })
+        // 旧版本客户端不发送这个字段，读到的是0，表示不分队
+        let num_teams = self.read_number();
+        let mutual = self.read_number();
+        let expansion = self.read_number();
+        let expansion_ratio = self.read_number();
+        let number = self.read_number();
+        let mut expansion_steps = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let secs = self.read_number();
+            let diff = self.read_decimal()?;
+            expansion_steps.push((secs, diff));
+        }
+        let max_rank_diff = self.read_decimal()?;
+        let min_players = self.read_number();
+        let fill_after_secs = self.read_number();
+        let priority = self.read_number();
+        let strategy = self.read_string();
+        let number = self.read_number();
+        let mut roles = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let role = self.read_string();
+            let count = self.read_number();
+            roles.push((role, count));
+        }
+        let latency_threshold = self.read_number();
+        let latency_speed = self.read_number();
+        let max_latency_threshold = self.read_number();
+        let recent_cooldown_secs = self.read_number();
+        let rating_system = self.read_number();
+        let rating_param = self.read_number();
+        let draw_beta = self.read_decimal()?;
+        let min_draw_quality = self.read_number();
+        let draw_relax_secs = self.read_number();
+        let accept_timeout_secs = self.read_number();
+        let bot_fill_after_secs = self.read_number();
+        let bot_min_players = self.read_number();
+        let number = self.read_number();
+        let mut attributes = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let key = self.read_string();
+            let kind = self.read_number();
+            let relax_secs = self.read_number();
+            attributes.push((key, kind, relax_secs));
+        }
+        Ok(Packet::AddArena {
+            arena,
+            num_players,
+            num_teams,
+            mutual,
+            expansion,
+            expansion_ratio,
+            expansion_steps,
+            max_rank_diff,
+            min_players,
+            fill_after_secs,
+            priority,
+            strategy,
+            roles,
+            latency_threshold,
+            latency_speed,
+            max_latency_threshold,
+            recent_cooldown_secs,
+            rating_system,
+            rating_param,
+            draw_beta,
+            min_draw_quality,
+            draw_relax_secs,
+            accept_timeout_secs,
+            bot_fill_after_secs,
+            bot_min_players,
+            attributes,
+        })
     }
     #[inline]
     fn read_v1_remove_arena(&mut self) -> Result<Packet, PacketFormat> {
@@ -267,10 +848,35 @@ impl CharReader {
     fn read_v1_add_player(&mut self) -> Result<Packet, PacketFormat> {
         let arena = self.read_string();
         let player = self.read_string();
-        let rank = self.read_number();
+        let rank = self.read_decimal()?;
         let length = self.read_number();
-        let init_rank_diff = self.read_number();
-        let speed = self.read_number();
+        let init_rank_diff = self.read_decimal()?;
+        let speed = self.read_decimal()?;
+        let number = self.read_number();
+        let mut roles = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let count = self.read_number();
+            let member_roles = (0..count).map(|_| self.read_string()).collect();
+            roles.push(member_roles);
+        }
+        let number = self.read_number();
+        let mut latencies = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let region = self.read_string();
+            let latency = self.read_number();
+            latencies.push((region, latency));
+        }
+        let number = self.read_number();
+        let avoid = (0..number).map(|_| self.read_string()).collect();
+        let rank_deviation = self.read_decimal()?;
+        let number = self.read_number();
+        let mut attributes = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let key = self.read_string();
+            let count = self.read_number();
+            let values = (0..count).map(|_| self.read_string()).collect();
+            attributes.push((key, values));
+        }
         Ok(Packet::AddPlayer {
             arena,
             player,
@@ -278,6 +884,11 @@ impl CharReader {
             length,
             init_rank_diff,
             speed,
+            roles,
+            latencies,
+            avoid,
+            rank_deviation,
+            attributes,
         })
     }
     #[inline]
@@ -294,33 +905,95 @@ impl CharReader {
     #[inline]
     fn read_v1_connection_state(&mut self) -> Result<Packet, PacketFormat> {
         let number = self.read_number();
-        let player_info = DashMap::with_capacity(number as usize);
+        let mut player_info = Vec::with_capacity(number as usize);
         for _ in 0..number {
-            let player = self.read_string();
-            let arena = self.read_string();
-            let num_matched = self.read_number();
-            player_info.insert(player, (arena, num_matched));
+            player_info.push(PlayerState {
+                player: self.read_string(),
+                arena: self.read_string(),
+                num_matched: self.read_number(),
+                wait_secs: 0,
+                eta_secs: 0,
+                position: 0,
+            });
+        }
+        // 旧版本的服务器没有后面的字段，读到的是0
+        let number = self.read_number();
+        for state in player_info.iter_mut().take(number as usize) {
+            state.wait_secs = self.read_number();
+            state.eta_secs = self.read_number();
+            state.position = self.read_number();
         }
-        Ok(Packet::ConnectionState { player_info })
+        let number = self.read_number();
+        let mut arena_stats = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            arena_stats.push(ArenaStats {
+                arena: self.read_string(),
+                matches: self.read_number(),
+                quality: self.read_quality(),
+            });
+        }
+        Ok(Packet::ConnectionState {
+            player_info,
+            arena_stats,
+        })
     }
     #[inline]
     fn read_v1_match_success(&mut self) -> Result<Packet, PacketFormat> {
         let arena = self.read_string();
         let stage_request_id = self.read_number();
+        let players = self.read_matched_parties();
+        let quality = self.read_quality();
         let number = self.read_number();
-        let mut players = Vec::with_capacity(number as usize);
+        let mut bots = Vec::with_capacity(number as usize);
         for _ in 0..number {
-            let player = self.read_string();
-            let length = self.read_number();
-            players.push((player, length));
+            let team = self.read_number() as usize;
+            let rank = self.read_decimal()?;
+            bots.push(Bot { team, rank });
         }
+        let number = self.read_number();
+        let attributes = (0..number)
+            .map(|_| (self.read_string(), self.read_string()))
+            .collect();
         Ok(Packet::MatchSuccess {
             arena,
             stage_request_id,
             players,
+            quality,
+            bots,
+            attributes,
         })
     }
     #[inline]
+    fn read_quality(&mut self) -> MatchQuality {
+        MatchQuality {
+            rank_spread: self.read_number(),
+            rank_std_dev: self.read_number(),
+            team_imbalance: self.read_number(),
+            max_wait_secs: self.read_number(),
+            avg_wait_secs: self.read_number(),
+        }
+    }
+    #[inline]
+    fn read_matched_parties(&mut self) -> Vec<MatchedParty> {
+        let number = self.read_number();
+        let mut players = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            players.push(MatchedParty {
+                player: self.read_string(),
+                length: self.read_number(),
+                team: 0,
+                roles: Vec::new(),
+            });
+        }
+        let number = self.read_number();
+        for party in players.iter_mut().take(number as usize) {
+            party.team = self.read_number();
+            let count = self.read_number();
+            party.roles = (0..count).map(|_| self.read_string()).collect();
+        }
+        players
+    }
+    #[inline]
     fn read_v1_match_failure(&mut self) -> Result<Packet, PacketFormat> {
         let arena = self.read_string();
         let error_id = self.read_number();
@@ -344,4 +1017,485 @@ impl CharReader {
         let error = self.read_string();
         Ok(Packet::FormatError { error })
     }
+    #[inline]
+    fn read_v1_match_result(&mut self) -> Result<Packet, PacketFormat> {
+        let stage_request_id = self.read_number();
+        let number = self.read_number();
+        let placements = (0..number).map(|_| self.read_number()).collect();
+        Ok(Packet::MatchResult {
+            stage_request_id,
+            placements,
+        })
+    }
+    #[inline]
+    fn read_v1_dequeued(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let player = self.read_string();
+        let matched_arena = self.read_string();
+        Ok(Packet::Dequeued {
+            arena,
+            player,
+            matched_arena,
+        })
+    }
+    #[inline]
+    fn read_v1_backfill(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let stage_request_id = self.read_number();
+        let rank = self.read_decimal()?;
+        let rank_diff = self.read_decimal()?;
+        let speed = self.read_decimal()?;
+        let number = self.read_number();
+        let open_slots = (0..number).map(|_| self.read_number()).collect();
+        let region = self.read_string();
+        let number = self.read_number();
+        let attributes = (0..number)
+            .map(|_| (self.read_string(), self.read_string()))
+            .collect();
+        Ok(Packet::Backfill {
+            arena,
+            stage_request_id,
+            rank,
+            rank_diff,
+            speed,
+            open_slots,
+            region,
+            attributes,
+        })
+    }
+    #[inline]
+    fn read_v1_backfill_success(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let stage_request_id = self.read_number();
+        let players = self.read_matched_parties();
+        Ok(Packet::BackfillSuccess {
+            arena,
+            stage_request_id,
+            players,
+        })
+    }
+    #[inline]
+    fn read_v1_pending_match(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let pending_id = self.read_number();
+        let players = self.read_matched_parties();
+        let timeout_secs = self.read_number();
+        Ok(Packet::PendingMatch {
+            arena,
+            pending_id,
+            players,
+            timeout_secs,
+        })
+    }
+    #[inline]
+    fn read_v1_accept_match(&mut self) -> Result<Packet, PacketFormat> {
+        let pending_id = self.read_number();
+        let player = self.read_string();
+        let accepted = self.read_number();
+        Ok(Packet::AcceptMatch {
+            pending_id,
+            player,
+            accepted,
+        })
+    }
+    #[inline]
+    fn read_v1_match_cancelled(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let pending_id = self.read_number();
+        let number = self.read_number();
+        let requeued = (0..number).map(|_| self.read_string()).collect();
+        let number = self.read_number();
+        let removed = (0..number).map(|_| self.read_string()).collect();
+        Ok(Packet::MatchCancelled {
+            arena,
+            pending_id,
+            requeued,
+            removed,
+        })
+    }
+    #[inline]
+    fn read_v1_update_player(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let player = self.read_string();
+        let rank = self.read_decimal()?;
+        let length = self.read_number();
+        let speed = self.read_decimal()?;
+        let number = self.read_number();
+        let mut roles = Vec::with_capacity(number as usize);
+        for _ in 0..number {
+            let count = self.read_number();
+            let member_roles = (0..count).map(|_| self.read_string()).collect();
+            roles.push(member_roles);
+        }
+        Ok(Packet::UpdatePlayer {
+            arena,
+            player,
+            rank,
+            length,
+            speed,
+            roles,
+        })
+    }
+    #[inline]
+    fn read_v1_update_player_failure(&mut self) -> Result<Packet, PacketFormat> {
+        let arena = self.read_string();
+        let player = self.read_string();
+        let error_msg = self.read_string();
+        Ok(Packet::UpdatePlayerFailure {
+            arena,
+            player,
+            error_msg,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 编码再解码，解码的结果重新编码以后和原来相同
+    fn round_trip(packet: Packet) -> Packet {
+        let text = packet.to_string();
+        let decoded = text.parse::<Packet>().unwrap();
+        assert_eq!(decoded.to_string(), text);
+        decoded
+    }
+
+    fn party(player: &str, team: u64, roles: &[&str]) -> MatchedParty {
+        MatchedParty {
+            player: player.to_string(),
+            length: roles.len().max(1) as u64,
+            team,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn connection_state_round_trip() {
+        let player_info = vec![
+            PlayerState {
+                player: "alice".to_string(),
+                arena: "solo".to_string(),
+                num_matched: 3,
+                wait_secs: 12,
+                eta_secs: 30,
+                position: 1,
+            },
+            PlayerState {
+                player: "alice".to_string(),
+                arena: "duo".to_string(),
+                num_matched: 1,
+                wait_secs: 5,
+                eta_secs: 0,
+                position: 2,
+            },
+        ];
+        let arena_stats = vec![ArenaStats {
+            arena: "solo".to_string(),
+            matches: 7,
+            quality: MatchQuality {
+                rank_spread: 40,
+                rank_std_dev: 15,
+                team_imbalance: 0,
+                max_wait_secs: 90,
+                avg_wait_secs: 20,
+            },
+        }];
+        let packet = Packet::ConnectionState {
+            player_info: player_info.clone(),
+            arena_stats: arena_stats.clone(),
+        };
+        // 列表的格式和最初的版本相同，新的字段都在后面
+        let text = packet.to_string();
+        assert!(text.starts_with("1,6,2,5,alice,4,solo,3,5,alice,3,duo,1,2,"));
+        match round_trip(packet) {
+            Packet::ConnectionState {
+                player_info: decoded,
+                arena_stats: decoded_stats,
+            } => {
+                assert_eq!(decoded, player_info);
+                assert_eq!(decoded_stats, arena_stats);
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn connection_state_from_old_server() {
+        match "1,6,1,5,alice,4,solo,3".parse::<Packet>().unwrap() {
+            Packet::ConnectionState {
+                player_info,
+                arena_stats,
+            } => {
+                assert!(arena_stats.is_empty());
+                assert_eq!(player_info.len(), 1);
+                assert_eq!(player_info[0].num_matched, 3);
+                assert_eq!(player_info[0].wait_secs, 0);
+                assert_eq!(player_info[0].position, 0);
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn match_success_from_old_server() {
+        match "1,7,6,ranked,42,2,1,a,1,1,b,2".parse::<Packet>().unwrap() {
+            Packet::MatchSuccess { players, .. } => {
+                assert_eq!(players.len(), 2);
+                assert_eq!((players[1].player.as_str(), players[1].length), ("b", 2));
+                assert_eq!(players[1].team, 0);
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn decimal_out_of_range() {
+        // 位数太多的小数会变成无穷大，整个包按格式错误处理
+        let huge = "9".repeat(400);
+        assert!(format!("1,17,1,a,1,p,1,1,{huge}")
+            .parse::<Packet>()
+            .is_err());
+        assert!(format!("1,17,1,a,1,p,1,1,{huge}.5")
+            .parse::<Packet>()
+            .is_err());
+        assert!("1,17,1,a,1,p,1,1,2.5".parse::<Packet>().is_ok());
+    }
+
+    #[test]
+    fn match_success_round_trip() {
+        let players = vec![party("a", 0, &["tank", "dps"]), party("b", 1, &[])];
+        let packet = Packet::MatchSuccess {
+            arena: "ranked".to_string(),
+            stage_request_id: 42,
+            players: players.clone(),
+            quality: MatchQuality {
+                rank_spread: 120,
+                rank_std_dev: 40,
+                team_imbalance: 10,
+                max_wait_secs: 30,
+                avg_wait_secs: 15,
+            },
+            bots: vec![Bot {
+                team: 1,
+                rank: -12.5,
+            }],
+            attributes: vec![("map".to_string(), "m1".to_string())],
+        };
+        // 小队列表的格式和最初的版本相同，旧版本的客户端按(名称, 人数)读取
+        assert!(packet
+            .to_string()
+            .starts_with("1,7,6,ranked,42,2,1,a,2,1,b,1,2,"));
+        match round_trip(packet) {
+            Packet::MatchSuccess {
+                stage_request_id,
+                players: decoded,
+                quality,
+                bots,
+                attributes,
+                ..
+            } => {
+                assert_eq!(stage_request_id, 42);
+                assert_eq!(decoded, players);
+                assert_eq!(quality.avg_wait_secs, 15);
+                assert_eq!((bots[0].team, bots[0].rank), (1, -12.5));
+                assert_eq!(attributes, vec![("map".to_string(), "m1".to_string())]);
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn add_arena_round_trip() {
+        let packet = Packet::AddArena {
+            arena: "ranked".to_string(),
+            num_players: 10,
+            num_teams: 2,
+            mutual: 1,
+            expansion: 2,
+            expansion_ratio: 1100,
+            expansion_steps: vec![(10, 50.0), (30, 0.0)],
+            max_rank_diff: 300.5,
+            min_players: 6,
+            fill_after_secs: 60,
+            priority: 2,
+            strategy: "rank".to_string(),
+            roles: vec![("tank".to_string(), 1), ("dps".to_string(), 4)],
+            latency_threshold: 80,
+            latency_speed: 5,
+            max_latency_threshold: 200,
+            recent_cooldown_secs: 600,
+            rating_system: 2,
+            rating_param: 500,
+            draw_beta: 4.5,
+            min_draw_quality: 400,
+            draw_relax_secs: 120,
+            accept_timeout_secs: 15,
+            bot_fill_after_secs: 90,
+            bot_min_players: 4,
+            attributes: vec![("lang".to_string(), 0, 0), ("map".to_string(), 2, 45)],
+        };
+        match round_trip(packet) {
+            Packet::AddArena {
+                expansion_steps,
+                draw_beta,
+                bot_min_players,
+                attributes,
+                ..
+            } => {
+                assert_eq!(expansion_steps, vec![(10, 50.0), (30, 0.0)]);
+                assert_eq!(draw_beta, 4.5);
+                assert_eq!(bot_min_players, 4);
+                assert_eq!(attributes[1], ("map".to_string(), 2, 45));
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn add_and_update_player_round_trip() {
+        let roles = vec![vec!["tank".to_string()], Vec::new()];
+        let packet = Packet::AddPlayer {
+            arena: "ranked".to_string(),
+            player: "alice".to_string(),
+            rank: -1.5,
+            length: 2,
+            init_rank_diff: 20.0,
+            speed: 2.5,
+            roles: roles.clone(),
+            latencies: vec![("eu".to_string(), 40)],
+            avoid: vec!["bob".to_string()],
+            rank_deviation: 80.0,
+            attributes: vec![("map".to_string(), vec!["m1".to_string(), "m2".to_string()])],
+        };
+        match round_trip(packet) {
+            Packet::AddPlayer {
+                rank,
+                roles: decoded,
+                latencies,
+                avoid,
+                attributes,
+                ..
+            } => {
+                assert_eq!(rank, -1.5);
+                assert_eq!(decoded, roles);
+                assert_eq!(latencies, vec![("eu".to_string(), 40)]);
+                assert_eq!(avoid, vec!["bob".to_string()]);
+                assert_eq!(attributes[0].1.len(), 2);
+            }
+            other => panic!("{other:?}"),
+        }
+        let packet = Packet::UpdatePlayer {
+            arena: "ranked".to_string(),
+            player: "alice".to_string(),
+            rank: 1200.0,
+            length: 2,
+            speed: 3.0,
+            roles: roles.clone(),
+        };
+        match round_trip(packet) {
+            Packet::UpdatePlayer {
+                rank,
+                roles: decoded,
+                ..
+            } => {
+                assert_eq!(rank, 1200.0);
+                assert_eq!(decoded, roles);
+            }
+            other => panic!("{other:?}"),
+        }
+        round_trip(Packet::UpdatePlayerFailure {
+            arena: "ranked".to_string(),
+            player: "alice".to_string(),
+            error_msg: "not queued".to_string(),
+        });
+    }
+
+    #[test]
+    fn backfill_round_trip() {
+        let packet = Packet::Backfill {
+            arena: "ranked".to_string(),
+            stage_request_id: 7,
+            rank: 1500.0,
+            rank_diff: 100.0,
+            speed: 5.0,
+            open_slots: vec![1, 0],
+            region: "eu".to_string(),
+            attributes: vec![("map".to_string(), "m2".to_string())],
+        };
+        match round_trip(packet) {
+            Packet::Backfill {
+                open_slots,
+                region,
+                attributes,
+                ..
+            } => {
+                assert_eq!(open_slots, vec![1, 0]);
+                assert_eq!(region, "eu");
+                assert_eq!(attributes, vec![("map".to_string(), "m2".to_string())]);
+            }
+            other => panic!("{other:?}"),
+        }
+        round_trip(Packet::BackfillSuccess {
+            arena: "ranked".to_string(),
+            stage_request_id: 7,
+            players: vec![party("c", 0, &[])],
+        });
+    }
+
+    #[test]
+    fn accept_phase_round_trip() {
+        let players = vec![party("a", 0, &[]), party("b", 1, &[])];
+        match round_trip(Packet::PendingMatch {
+            arena: "ranked".to_string(),
+            pending_id: 3,
+            players: players.clone(),
+            timeout_secs: 15,
+        }) {
+            Packet::PendingMatch {
+                players: decoded,
+                timeout_secs,
+                ..
+            } => {
+                assert_eq!(decoded, players);
+                assert_eq!(timeout_secs, 15);
+            }
+            other => panic!("{other:?}"),
+        }
+        round_trip(Packet::AcceptMatch {
+            pending_id: 3,
+            player: "a".to_string(),
+            accepted: 1,
+        });
+        match round_trip(Packet::MatchCancelled {
+            arena: "ranked".to_string(),
+            pending_id: 3,
+            requeued: vec!["a".to_string()],
+            removed: vec!["b".to_string()],
+        }) {
+            Packet::MatchCancelled {
+                requeued, removed, ..
+            } => {
+                assert_eq!(requeued, vec!["a".to_string()]);
+                assert_eq!(removed, vec!["b".to_string()]);
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn result_and_dequeued_round_trip() {
+        match round_trip(Packet::MatchResult {
+            stage_request_id: 42,
+            placements: vec![1, 0, 2],
+        }) {
+            Packet::MatchResult { placements, .. } => assert_eq!(placements, vec![1, 0, 2]),
+            other => panic!("{other:?}"),
+        }
+        round_trip(Packet::Dequeued {
+            arena: "duo".to_string(),
+            player: "alice".to_string(),
+            matched_arena: "solo".to_string(),
+        });
+    }
 }
@@ -0,0 +1,132 @@
+// 对局质量：每次匹配成功时计算，发送给大厅服务器和中心服务器，并且按匹配池累计，用来调整区间参数
+use crate::arena::Player;
+use std::fmt;
+
+// 一局的质量指标。每个小队按人数计算，也就是小队的每位成员都算一次
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize)]
+pub struct MatchQuality {
+    // 最高分和最低分的差，四舍五入
+    pub rank_spread: u64,
+    // 分数的标准差，四舍五入
+    pub rank_std_dev: u64,
+    // 各队伍总分（分数 × 人数）最高和最低的差，四舍五入。不分队时为0
+    pub team_imbalance: u64,
+    // 等待最久的玩家等待的秒数
+    pub max_wait_secs: u64,
+    // 平均等待的秒数，四舍五入
+    pub avg_wait_secs: u64,
+}
+
+impl MatchQuality {
+    // teams和parties一一对应，是每个小队分到的队伍编号
+    pub fn of<T>(parties: &[(T, Player)], teams: &[usize], num_teams: usize) -> Self {
+        let num_players: usize = parties.iter().map(|(_, player)| player.length).sum();
+        if num_players == 0 {
+            return MatchQuality::default();
+        }
+        let n = num_players as f64;
+        let ranks = parties.iter().filter(|(_, player)| player.length > 0);
+        let rank_max = ranks
+            .clone()
+            .map(|(_, player)| player.rank)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let rank_min = ranks
+            .map(|(_, player)| player.rank)
+            .fold(f64::INFINITY, f64::min);
+        let mean = parties
+            .iter()
+            .map(|(_, player)| player.rank * player.length as f64)
+            .sum::<f64>()
+            / n;
+        let variance = parties
+            .iter()
+            .map(|(_, player)| (player.rank - mean).powi(2) * player.length as f64)
+            .sum::<f64>()
+            / n;
+        let mut totals = vec![0.0; usize::max(num_teams, 1)];
+        for ((_, player), &team) in parties.iter().zip(teams) {
+            totals[team] += player.rank * player.length as f64;
+        }
+        let team_max = totals.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let team_min = totals.iter().copied().fold(f64::INFINITY, f64::min);
+        let waits = parties
+            .iter()
+            .map(|(_, player)| (player.enqueued_at.elapsed().as_secs_f64(), player.length));
+        let max_wait = waits
+            .clone()
+            .filter(|&(_, length)| length > 0)
+            .map(|(wait, _)| wait)
+            .fold(0.0, f64::max);
+        let avg_wait = waits
+            .map(|(wait, length)| wait * length as f64)
+            .sum::<f64>()
+            / n;
+        MatchQuality {
+            rank_spread: (rank_max - rank_min).round() as u64,
+            rank_std_dev: variance.sqrt().round() as u64,
+            team_imbalance: (team_max - team_min).round() as u64,
+            max_wait_secs: max_wait as u64,
+            avg_wait_secs: avg_wait.round() as u64,
+        }
+    }
+}
+
+// 一个匹配池累计的对局质量
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QualityStats {
+    // 匹配成功的局数
+    pub matches: u64,
+    sum_rank_spread: u64,
+    sum_rank_std_dev: u64,
+    sum_team_imbalance: u64,
+    sum_avg_wait_secs: u64,
+    // 所有对局中等待最久的秒数
+    pub max_wait_secs: u64,
+}
+
+impl QualityStats {
+    pub fn record(&mut self, quality: &MatchQuality) {
+        self.matches += 1;
+        self.sum_rank_spread = self.sum_rank_spread.saturating_add(quality.rank_spread);
+        self.sum_rank_std_dev = self.sum_rank_std_dev.saturating_add(quality.rank_std_dev);
+        self.sum_team_imbalance = self
+            .sum_team_imbalance
+            .saturating_add(quality.team_imbalance);
+        self.sum_avg_wait_secs = self.sum_avg_wait_secs.saturating_add(quality.avg_wait_secs);
+        self.max_wait_secs = u64::max(self.max_wait_secs, quality.max_wait_secs);
+    }
+
+    // 每一项是所有对局的平均值，四舍五入。max_wait_secs是所有对局中等待最久的秒数
+    pub fn averages(&self) -> MatchQuality {
+        MatchQuality {
+            rank_spread: self.average(self.sum_rank_spread).round() as u64,
+            rank_std_dev: self.average(self.sum_rank_std_dev).round() as u64,
+            team_imbalance: self.average(self.sum_team_imbalance).round() as u64,
+            max_wait_secs: self.max_wait_secs,
+            avg_wait_secs: self.average(self.sum_avg_wait_secs).round() as u64,
+        }
+    }
+
+    fn average(&self, sum: u64) -> f64 {
+        if self.matches == 0 {
+            0.0
+        } else {
+            sum as f64 / self.matches as f64
+        }
+    }
+}
+
+impl fmt::Display for QualityStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "共 {} 局，平均分数跨度 {:.1}，平均分数标准差 {:.1}，平均队伍总分差 {:.1}，平均等待 {:.1} 秒，最长等待 {} 秒",
+            self.matches,
+            self.average(self.sum_rank_spread),
+            self.average(self.sum_rank_std_dev),
+            self.average(self.sum_team_imbalance),
+            self.average(self.sum_avg_wait_secs),
+            self.max_wait_secs
+        )
+    }
+}
@@ -0,0 +1,357 @@
+// 评分系统：收到比赛结果以后更新玩家的评分。匹配池设置了评分系统时，AddPlayer可以不发送分数，直接使用这里保存的评分
+use dashmap::DashMap;
+use std::{borrow::Borrow, cmp::Ordering, f64::consts::PI, hash::Hash};
+
+// Glicko-2的分数和内部刻度之间的换算比例
+const GLICKO2_SCALE: f64 = 173.7178;
+// Glicko-2计算波动率时的收敛精度
+const GLICKO2_EPSILON: f64 = 0.000001;
+
+// 初始评分
+const INITIAL_RATING: f64 = 1500.0;
+// Glicko-2的初始评分偏差和初始波动率
+const INITIAL_DEVIATION: f64 = 350.0;
+const INITIAL_VOLATILITY: f64 = 0.06;
+
+#[derive(Clone, Copy, Debug)]
+pub enum RatingSystem {
+    // k是每局评分变化的最大值
+    Elo { k: f64 },
+    // tau限制波动率的变化速度，通常在0.3到1.2之间
+    Glicko2 { tau: f64 },
+}
+
+// 一位玩家（或者一个小队）的评分。Elo只使用rating
+#[derive(Clone, Copy, Debug)]
+pub struct Rating {
+    pub rating: f64,
+    // 评分偏差，越大表示评分越不确定
+    pub deviation: f64,
+    // 波动率，表示玩家的水平变化得有多快
+    pub volatility: f64,
+}
+
+impl RatingSystem {
+    // 没有保存评分的玩家使用的评分
+    pub fn initial(&self) -> Rating {
+        Rating {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            volatility: INITIAL_VOLATILITY,
+        }
+    }
+
+    // teams[i]是第i个队伍每个小队的(评分, 人数)，placements[i]是第i个队伍的名次，
+    // 0表示第一名，名次相同表示平局。每两个队伍之间都算作进行了一局
+    // 返回和teams一一对应的新评分
+    pub fn update(&self, teams: &[Vec<(Rating, usize)>], placements: &[u64]) -> Vec<Vec<Rating>> {
+        let composites = teams.iter().map(|team| composite(team)).collect::<Vec<_>>();
+        let games_of = |i: usize| games(i, &composites, placements);
+        match *self {
+            RatingSystem::Elo { k } => (0..teams.len())
+                .map(|i| {
+                    let games = games_of(i);
+                    let delta = games
+                        .iter()
+                        .map(|(opponent, score)| {
+                            score - elo_expected(composites[i].rating, opponent.rating)
+                        })
+                        .sum::<f64>()
+                        * k
+                        / usize::max(games.len(), 1) as f64;
+                    // 同一个队伍的小队评分变化相同
+                    teams[i]
+                        .iter()
+                        .map(|&(rating, _length)| Rating {
+                            rating: rating.rating + delta,
+                            ..rating
+                        })
+                        .collect()
+                })
+                .collect(),
+            RatingSystem::Glicko2 { tau } => (0..teams.len())
+                .map(|i| {
+                    let games = games_of(i);
+                    teams[i]
+                        .iter()
+                        .map(|&(rating, _length)| glicko2_update(rating, &games, tau))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+// 一个匹配池的评分系统保存的评分，小队以队长的名义保存。不属于匹配池，删除匹配池时保留
+pub struct RatingStore<T> {
+    ratings: DashMap<T, Rating>,
+}
+
+impl<T: Hash + Eq> Default for RatingStore<T> {
+    fn default() -> Self {
+        RatingStore {
+            ratings: DashMap::new(),
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> RatingStore<T> {
+    // 保存的评分，没有保存过时返回None
+    pub fn rating<Q>(&self, id: &Q) -> Option<Rating>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.ratings.get(id).map(|rating| *rating)
+    }
+
+    // 按比赛结果更新评分。parties中每一项是(玩家, 小队人数, 队伍编号)，placements[i]是第i个队伍的名次。
+    // 不分队（num_teams为1）时每个小队各自为战，placements[i]是parties[i]的名次
+    // 返回每个小队的新评分
+    pub fn update(
+        &self,
+        system: &RatingSystem,
+        parties: &[(T, usize, usize)],
+        placements: &[u64],
+        num_teams: usize,
+    ) -> Vec<(T, Rating)> {
+        let mut teams = vec![Vec::new(); placements.len()];
+        let mut members = vec![Vec::new(); placements.len()];
+        for (i, (id, length, team)) in parties.iter().enumerate() {
+            let team = if num_teams == 1 { i } else { *team };
+            let rating = self.rating(id).unwrap_or_else(|| system.initial());
+            teams[team].push((rating, *length));
+            members[team].push(id.clone());
+        }
+        let updated = system.update(&teams, placements);
+        let mut ans = Vec::with_capacity(parties.len());
+        for (ids, ratings) in members.into_iter().zip(updated) {
+            for (id, rating) in ids.into_iter().zip(ratings) {
+                self.ratings.insert(id.clone(), rating);
+                ans.push((id, rating));
+            }
+        }
+        ans
+    }
+}
+
+// 第i个队伍对其他每个队伍的(对手的综合评分, 得分)
+fn games(i: usize, composites: &[Rating], placements: &[u64]) -> Vec<(Rating, f64)> {
+    (0..composites.len())
+        .filter(|&j| j != i)
+        .map(|j| {
+            let score = match placements[i].cmp(&placements[j]) {
+                Ordering::Less => 1.0,
+                Ordering::Equal => 0.5,
+                Ordering::Greater => 0.0,
+            };
+            (composites[j], score)
+        })
+        .collect()
+}
+
+// 两位玩家打成平局的概率（TrueSkill的对局质量），每位玩家是(分数, 评分偏差)
+pub fn draw_quality(beta: f64, a: (f64, f64), b: (f64, f64)) -> f64 {
+    team_draw_quality(beta, &[(a.0, a.1, 1)], &[(b.0, b.1, 1)])
+}
+
+// 两个队伍打成平局的概率，每个队伍的每一项是(分数, 评分偏差, 人数)。队伍的实力按所有成员的分数之和计算
+pub fn team_draw_quality(beta: f64, a: &[(f64, f64, usize)], b: &[(f64, f64, usize)]) -> f64 {
+    let sum = |team: &[(f64, f64, usize)], f: fn(f64, f64) -> f64| {
+        team.iter()
+            .map(|&(rank, deviation, length)| f(rank, deviation) * length as f64)
+            .sum::<f64>()
+    };
+    let players = a
+        .iter()
+        .chain(b)
+        .map(|&(_, _, length)| length)
+        .sum::<usize>() as f64;
+    let mean_diff = sum(a, |rank, _| rank) - sum(b, |rank, _| rank);
+    let variance = players * beta * beta
+        + sum(a, |_, deviation| deviation * deviation)
+        + sum(b, |_, deviation| deviation * deviation);
+    if variance <= 0.0 {
+        return if mean_diff == 0.0 { 1.0 } else { 0.0 };
+    }
+    (players * beta * beta / variance).sqrt() * (-mean_diff * mean_diff / (2.0 * variance)).exp()
+}
+
+// 一个队伍的综合评分：评分按人数加权平均，评分偏差按人数取平方平均
+fn composite(team: &[(Rating, usize)]) -> Rating {
+    let total = team.iter().map(|&(_rating, length)| length).sum::<usize>();
+    if total == 0 {
+        return Rating {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            volatility: INITIAL_VOLATILITY,
+        };
+    }
+    let weighted = |f: fn(&Rating) -> f64| {
+        team.iter()
+            .map(|(rating, length)| f(rating) * *length as f64)
+            .sum::<f64>()
+            / total as f64
+    };
+    Rating {
+        rating: weighted(|r| r.rating),
+        deviation: weighted(|r| r.deviation * r.deviation).sqrt(),
+        volatility: weighted(|r| r.volatility),
+    }
+}
+
+// Elo中评分为rating的一方战胜opponent的期望得分
+fn elo_expected(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+// Glicko-2：player在一个评分周期内进行了games中的这些对局，(对手的评分, 得分)，返回新的评分
+// 算法见 http://www.glicko.net/glicko/glicko2.pdf
+fn glicko2_update(player: Rating, games: &[(Rating, f64)], tau: f64) -> Rating {
+    let mu = (player.rating - INITIAL_RATING) / GLICKO2_SCALE;
+    let phi = player.deviation / GLICKO2_SCALE;
+    let sigma = player.volatility;
+    if games.is_empty() {
+        // 没有对局时只增加评分偏差
+        let phi_star = (phi * phi + sigma * sigma).sqrt();
+        return Rating {
+            deviation: phi_star * GLICKO2_SCALE,
+            ..player
+        };
+    }
+    let g = |phi: f64| 1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt();
+    // (g(φj), E(μ, μj, φj), 得分)
+    let terms = games
+        .iter()
+        .map(|(opponent, score)| {
+            let mu_j = (opponent.rating - INITIAL_RATING) / GLICKO2_SCALE;
+            let g_j = g(opponent.deviation / GLICKO2_SCALE);
+            let e = 1.0 / (1.0 + (-g_j * (mu - mu_j)).exp());
+            (g_j, e, *score)
+        })
+        .collect::<Vec<_>>();
+    let v = 1.0
+        / terms
+            .iter()
+            .map(|(g_j, e, _)| g_j * g_j * e * (1.0 - e))
+            .sum::<f64>();
+    let improvement = terms.iter().map(|(g_j, e, s)| g_j * (s - e)).sum::<f64>();
+    let delta = v * improvement;
+    // 用Illinois算法求新的波动率
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+            - (x - a) / (tau * tau)
+    };
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+    let (mut f_a, mut f_b) = (f(big_a), f(big_b));
+    while (big_b - big_a).abs() > GLICKO2_EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            (big_a, f_a) = (big_b, f_b);
+        } else {
+            f_a /= 2.0;
+        }
+        (big_b, f_b) = (big_c, f_c);
+    }
+    let sigma_new = (big_a / 2.0).exp();
+    let phi_star = (phi * phi + sigma_new * sigma_new).sqrt();
+    let phi_new = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let mu_new = mu + phi_new * phi_new * improvement;
+    Rating {
+        rating: mu_new * GLICKO2_SCALE + INITIAL_RATING,
+        deviation: phi_new * GLICKO2_SCALE,
+        volatility: sigma_new,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            volatility: INITIAL_VOLATILITY,
+        }
+    }
+
+    #[test]
+    fn free_for_all_ratings() {
+        // 不分队时每个小队单独排名
+        let store = RatingStore::default();
+        let system = RatingSystem::Elo { k: 32.0 };
+        let parties = [("a", 1, 0), ("b", 1, 0), ("c", 2, 0)];
+        let ratings = store.update(&system, &parties, &[1, 0, 2], 1);
+        let rating = |id: &'static str| store.rating(&id).unwrap().rating;
+        assert_eq!(ratings.len(), 3);
+        assert!(rating("b") > 1500.0);
+        assert!((rating("a") - 1500.0).abs() < 1e-9);
+        assert!(rating("c") < 1500.0);
+    }
+
+    #[test]
+    fn elo_two_teams() {
+        let system = RatingSystem::Elo { k: 32.0 };
+        let teams = vec![
+            vec![(rating(1500.0, 0.0), 1)],
+            vec![(rating(1500.0, 0.0), 1)],
+        ];
+        let ans = system.update(&teams, &[0, 1]);
+        assert!((ans[0][0].rating - 1516.0).abs() < 1e-9);
+        assert!((ans[1][0].rating - 1484.0).abs() < 1e-9);
+        // 平局时评分相同的玩家不变
+        let ans = system.update(&teams, &[0, 0]);
+        assert!((ans[0][0].rating - 1500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn elo_party_shares_team_delta() {
+        let system = RatingSystem::Elo { k: 32.0 };
+        let teams = vec![
+            vec![(rating(1400.0, 0.0), 1), (rating(1600.0, 0.0), 1)],
+            vec![(rating(1500.0, 0.0), 2)],
+        ];
+        let ans = system.update(&teams, &[1, 0]);
+        assert!((ans[0][0].rating - 1384.0).abs() < 1e-9);
+        assert!((ans[0][1].rating - 1584.0).abs() < 1e-9);
+        assert!((ans[1][0].rating - 1516.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn draw_quality_prefers_close_and_certain() {
+        let beta = 4.1667;
+        // TrueSkill默认参数下两位新玩家的对局质量约为0.447
+        let q = draw_quality(beta, (25.0, 8.3333), (25.0, 8.3333));
+        assert!((q - 0.447).abs() < 0.001, "{q}");
+        assert!(draw_quality(beta, (25.0, 1.0), (25.0, 1.0)) > q);
+        assert!(draw_quality(beta, (25.0, 8.3333), (35.0, 8.3333)) < q);
+    }
+
+    #[test]
+    fn glicko2_paper_example() {
+        // Glickman的论文中的例子：1500/200的玩家赢了1400/30，输给了1550/100和1700/300
+        let player = rating(1500.0, 200.0);
+        let games = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+        let ans = glicko2_update(player, &games, 0.5);
+        assert!((ans.rating - 1464.06).abs() < 0.01, "{ans:?}");
+        assert!((ans.deviation - 151.52).abs() < 0.01, "{ans:?}");
+        assert!((ans.volatility - 0.05999).abs() < 0.00001, "{ans:?}");
+    }
+}
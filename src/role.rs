@@ -0,0 +1,208 @@
+// 角色分配：一局中每个队伍有固定的角色位置（例如1个坦克、1个治疗、3个输出），
+// 每位玩家可以接受若干个角色，用二分图匹配把玩家分配到角色位置上
+use crate::team::{distinct_teams, SEARCH_LIMIT};
+
+// 每位玩家可以接受的角色用位掩码表示，第i位为1表示可以接受第i个角色，所以一个匹配池最多64个角色
+pub const MAX_ROLES: usize = 64;
+
+// 可以接受所有角色
+pub const ANY_ROLE: u64 = u64::MAX;
+
+// members是每位玩家可以接受的角色，slots[i]是第i个角色的位置数量
+// 返回每位玩家分到的角色编号；有玩家分不到位置时返回None。位置可以有空余
+pub fn assign_roles(members: &[u64], slots: &[usize]) -> Option<Vec<usize>> {
+    // 把每个角色的每个位置展开成一个节点
+    let slot_roles = slots
+        .iter()
+        .enumerate()
+        .flat_map(|(role, &count)| std::iter::repeat_n(role, count))
+        .collect::<Vec<_>>();
+    if members.len() > slot_roles.len() {
+        return None;
+    }
+    let mut matching = Matching {
+        members,
+        slot_roles: &slot_roles,
+        owner: vec![None; slot_roles.len()],
+        visited: vec![false; slot_roles.len()],
+    };
+    for member in 0..members.len() {
+        matching.visited.fill(false);
+        if !matching.augment(member) {
+            return None;
+        }
+    }
+    let mut ans = vec![0; members.len()];
+    for (slot, owner) in matching.owner.iter().enumerate() {
+        if let Some(member) = owner {
+            ans[*member] = slot_roles[slot];
+        }
+    }
+    Some(ans)
+}
+
+// parties中每一项是一个小队所有成员可以接受的角色，按优先级从高到低排列。slots是每个队伍的角色位置
+// 从前往后贪心：加入这个小队以后，选中的小队仍然能分到num_teams个队伍里、并且所有人都能分到位置，
+// 就选中这个小队，直到恰好填满所有队伍的所有位置。小队不拆开
+// 返回选中的小队下标，从小到大；填不满时返回None
+pub fn choose_parties(
+    parties: &[Vec<u64>],
+    slots: &[usize],
+    num_teams: usize,
+) -> Option<Vec<usize>> {
+    let total = slots.iter().sum::<usize>() * num_teams;
+    let mut num_members = 0;
+    let mut chosen = Vec::new();
+    for (i, party) in parties.iter().enumerate() {
+        if num_members + party.len() > total {
+            continue;
+        }
+        chosen.push(i);
+        let chosen_parties = chosen.iter().map(|&j| &parties[j][..]).collect::<Vec<_>>();
+        if !can_split(&chosen_parties, slots, num_teams) {
+            chosen.pop();
+            continue;
+        }
+        num_members += party.len();
+        if num_members == total {
+            return Some(chosen);
+        }
+    }
+    None
+}
+
+// 判断这些小队能否分到num_teams个队伍里，并且每个队伍的成员都能分到这个队伍的角色位置。
+// 搜索超过节点数上限时当作不能分
+fn can_split(parties: &[&[u64]], slots: &[usize], num_teams: usize) -> bool {
+    let mut order = (0..parties.len()).collect::<Vec<_>>();
+    // 人数多的小队先放
+    order.sort_by_key(|&i| std::cmp::Reverse(parties[i].len()));
+    let mut search = Split {
+        parties,
+        slots,
+        order,
+        teams: vec![Vec::new(); num_teams],
+        visited: 0,
+    };
+    search.dfs(0)
+}
+
+struct Split<'a> {
+    parties: &'a [&'a [u64]],
+    slots: &'a [usize],
+    order: Vec<usize>,
+    // 每个队伍当前的成员
+    teams: Vec<Vec<u64>>,
+    visited: usize,
+}
+
+impl<'a> Split<'a> {
+    fn dfs(&mut self, k: usize) -> bool {
+        self.visited += 1;
+        if self.visited > SEARCH_LIMIT {
+            return false;
+        }
+        if k == self.order.len() {
+            return true;
+        }
+        let party = self.parties[self.order[k]];
+        for t in distinct_teams(0..self.teams.len(), |t| self.teams[t].is_empty()) {
+            let len = self.teams[t].len();
+            self.teams[t].extend_from_slice(party);
+            if assign_roles(&self.teams[t], self.slots).is_some() && self.dfs(k + 1) {
+                return true;
+            }
+            self.teams[t].truncate(len);
+        }
+        false
+    }
+}
+
+struct Matching<'a> {
+    members: &'a [u64],
+    // 每个位置对应的角色编号
+    slot_roles: &'a [usize],
+    // 每个位置当前分给了哪位玩家
+    owner: Vec<Option<usize>>,
+    visited: Vec<bool>,
+}
+
+impl<'a> Matching<'a> {
+    // 匈牙利算法：为member找一条增广路，找到时更新owner
+    fn augment(&mut self, member: usize) -> bool {
+        for slot in 0..self.slot_roles.len() {
+            if self.visited[slot] || self.members[member] & (1 << self.slot_roles[slot]) == 0 {
+                continue;
+            }
+            self.visited[slot] = true;
+            let owner = self.owner[slot];
+            if owner.is_none_or(|other| self.augment(other)) {
+                self.owner[slot] = Some(member);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TANK: u64 = 1 << 0;
+    const HEALER: u64 = 1 << 1;
+    const DPS: u64 = 1 << 2;
+
+    #[test]
+    fn assign_with_reassignment() {
+        // 第一位玩家先占了坦克位置，第二位玩家只能当坦克，需要把第一位玩家换到治疗
+        assert_eq!(
+            assign_roles(&[TANK | HEALER, TANK, DPS], &[1, 1, 1]),
+            Some(vec![1, 0, 2])
+        );
+    }
+
+    #[test]
+    fn assign_impossible() {
+        assert_eq!(assign_roles(&[TANK, TANK], &[1, 1, 3]), None);
+        assert_eq!(assign_roles(&[DPS, DPS], &[1, 1, 1]), None);
+        assert_eq!(assign_roles(&[ANY_ROLE; 3], &[1, 1]), None);
+    }
+
+    #[test]
+    fn assign_leaves_free_slots() {
+        assert_eq!(assign_roles(&[DPS, ANY_ROLE], &[1, 1, 3]), Some(vec![2, 0]));
+        assert_eq!(assign_roles(&[], &[1, 1, 3]), Some(vec![]));
+    }
+
+    #[test]
+    fn choose_skips_conflicting_parties() {
+        let parties = vec![
+            vec![TANK, DPS],
+            vec![TANK],
+            vec![DPS, DPS],
+            vec![HEALER | DPS],
+            vec![HEALER],
+        ];
+        // 第1个小队和第0个小队抢坦克位置；第3个小队选中以后只能当治疗
+        assert_eq!(choose_parties(&parties, &[1, 1, 3], 1), Some(vec![0, 2, 3]));
+        // 两个队伍时人数不够
+        assert_eq!(choose_parties(&parties, &[1, 1, 3], 2), None);
+    }
+
+    #[test]
+    fn choose_respects_teams() {
+        let parties = vec![
+            vec![TANK, HEALER],
+            vec![TANK],
+            vec![DPS, DPS],
+            vec![HEALER | DPS],
+            vec![ANY_ROLE; 2],
+        ];
+        // 每个队伍只有一个输出位置，第2个小队的两位成员不能在同一个队伍里
+        assert_eq!(
+            choose_parties(&parties, &[1, 1, 1], 2),
+            Some(vec![0, 1, 3, 4])
+        );
+    }
+}
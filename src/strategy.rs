@@ -0,0 +1,892 @@
+// 匹配策略：决定一个匹配池里排队的小队怎样组成一局
+use crate::arena::{self, ArenaConfig, Backfill, BotFill, Conflicts, Player, Priority};
+use crate::{attribute, knapsack, rating, role, team};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Debug,
+    hash::Hash,
+    sync::Arc,
+    time::Instant,
+};
+
+// 匹配成功的一局
+#[derive(Debug)]
+pub struct Group<T> {
+    // 选中的小队
+    pub parties: Vec<(T, Player)>,
+    // 和parties一一对应，每个小队分到的队伍编号
+    pub teams: Vec<usize>,
+    // 和parties一一对应，小队每位成员分到的角色编号。匹配池不区分角色时为空
+    pub roles: Vec<Vec<usize>>,
+    // 这一局所在的地区。匹配池不区分地区，或者小队都没有发送延迟时为None
+    pub region: Option<String>,
+    // 补满这一局的机器人，没有用机器人补位时为空
+    pub bots: Vec<Bot>,
+    // 这一局取得一致的属性，(属性名称, 取值)。匹配池不看属性，或者小队都不限制这个属性时没有这一项
+    pub attributes: Vec<(String, String)>,
+}
+
+// 用来补满一局的机器人，由游戏服务器创建AI
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Bot {
+    // 分到的队伍编号
+    pub team: usize,
+    // 机器人的分数，是这一局真人的平均分
+    pub rank: f64,
+}
+
+// 每个匹配池在注册时选择一种匹配策略，排位定时器每秒调用一次
+pub trait MatchStrategy<T>: Debug + Send + Sync {
+    // parties是匹配池中所有排队的小队，返回这一次能组成的所有对局，各局之间不能有重复的小队
+    // conflicts中互相冲突的玩家不能分到同一局
+    fn form_groups(
+        &self,
+        arena: &str,
+        config: &ArenaConfig,
+        parties: Vec<(T, Player)>,
+        conflicts: &Conflicts<T>,
+    ) -> Vec<Group<T>>;
+}
+
+// 按名称选择匹配策略，名称为空时使用默认的排位策略
+pub fn by_name<T>(name: &str) -> Option<Arc<dyn MatchStrategy<T>>>
+where
+    T: Hash + Eq + Clone + Debug + Send + Sync + 'static,
+{
+    match name {
+        "" | "rank" => Some(Arc::new(RankStrategy)),
+        "fifo" => Some(Arc::new(FifoStrategy)),
+        _ => None,
+    }
+}
+
+// 默认的排位策略：扫描线找出区间重叠最多的分数，再用背包问题选出一局并分队
+#[derive(Debug)]
+pub struct RankStrategy;
+
+impl<T> MatchStrategy<T> for RankStrategy
+where
+    T: Hash + Eq + Clone + Debug + Send + Sync,
+{
+    fn form_groups(
+        &self,
+        arena: &str,
+        config: &ArenaConfig,
+        mut parties: Vec<(T, Player)>,
+        conflicts: &Conflicts<T>,
+    ) -> Vec<Group<T>> {
+        let mut groups = Vec::new();
+        // 把能组成的对局全部组成，直到剩下的玩家无法再组成一局。
+        // 覆盖人数最多的分数组不成一局时（例如玩家互相冲突、角色凑不齐），继续尝试其他分数
+        loop {
+            // 用下标代替玩家，方便跳过候选玩家完全相同的分数
+            let indexed = parties
+                .iter()
+                .enumerate()
+                .map(|(i, (_name, player))| (i, player.clone()))
+                .collect::<Vec<_>>();
+            let mut tried = HashSet::new();
+            let targets = arena::rank_targets(&indexed, config.min_players);
+            let group = targets.into_iter().find_map(|target| {
+                let mut matched = arena::rank_match(&indexed, target, config.mutual)
+                    .into_iter()
+                    .map(|(i, _player)| i)
+                    .collect::<Vec<_>>();
+                matched.sort_unstable();
+                if !tried.insert(matched.clone()) {
+                    return None;
+                }
+                let matched = matched.into_iter().map(|i| parties[i].clone()).collect();
+                pick_group(arena, config, matched, conflicts)
+            });
+            let Some(group) = group else {
+                break;
+            };
+            remove_grouped(&mut parties, &group);
+            groups.push(group);
+        }
+        groups
+    }
+}
+
+// 休闲策略：不看分数，按进入匹配池的先后顺序组成一局
+#[derive(Debug)]
+pub struct FifoStrategy;
+
+impl<T> MatchStrategy<T> for FifoStrategy
+where
+    T: Hash + Eq + Clone + Debug + Send + Sync,
+{
+    fn form_groups(
+        &self,
+        arena: &str,
+        config: &ArenaConfig,
+        mut parties: Vec<(T, Player)>,
+        conflicts: &Conflicts<T>,
+    ) -> Vec<Group<T>> {
+        let mut groups = Vec::new();
+        while let Some(group) = pick_group(arena, config, parties.clone(), conflicts) {
+            remove_grouped(&mut parties, &group);
+            groups.push(group);
+        }
+        groups
+    }
+}
+
+pub fn remove_grouped<T: Hash + Eq>(parties: &mut Vec<(T, Player)>, group: &Group<T>) {
+    let grouped = group
+        .parties
+        .iter()
+        .map(|(id, _)| id)
+        .collect::<HashSet<_>>();
+    parties.retain(|(id, _)| !grouped.contains(id));
+}
+
+// 从候选的小队中选出一局并分队，候选的小队不够组成一局时返回None
+// 匹配池规定了属性时，一局的所有小队必须能接受同一种属性组合，等待时间长的小队想要的组合先尝试
+pub fn pick_group<T>(
+    arena: &str,
+    config: &ArenaConfig,
+    mut matched: Vec<(T, Player)>,
+    conflicts: &Conflicts<T>,
+) -> Option<Group<T>>
+where
+    T: Hash + Eq + Clone + Debug,
+{
+    if config.attributes.is_empty() {
+        return pick_group_by_region(arena, config, matched, conflicts);
+    }
+    let now = Instant::now();
+    matched.sort_by_key(|(_name, player)| player.enqueued_at);
+    let players = matched.iter().map(|(_name, player)| player);
+    for assignment in attribute::assignments(&config.attributes, players, now) {
+        let parties = matched
+            .iter()
+            .filter(|(_name, player)| {
+                attribute::accepts(&config.attributes, &assignment, player, now)
+            })
+            .cloned()
+            .collect();
+        if let Some(group) = pick_group_by_region(arena, config, parties, conflicts) {
+            return Some(Group {
+                attributes: attribute::agreed(&config.attributes, &assignment),
+                ..group
+            });
+        }
+    }
+    None
+}
+
+// 匹配池设置了延迟阈值时，一局的所有小队必须在同一个地区的延迟都不超过各自的阈值。
+// 等待时间长的小队可以开局的地区先尝试，同一个小队的多个地区按延迟从低到高尝试
+fn pick_group_by_region<T>(
+    arena: &str,
+    config: &ArenaConfig,
+    matched: Vec<(T, Player)>,
+    conflicts: &Conflicts<T>,
+) -> Option<Group<T>>
+where
+    T: Hash + Eq + Clone + Debug,
+{
+    let Some(latency) = config.latency else {
+        return pick_group_in(arena, config, matched, conflicts);
+    };
+    let now = Instant::now();
+    let thresholds = matched
+        .iter()
+        .map(|(_name, player)| {
+            latency.threshold_after(now.saturating_duration_since(player.enqueued_at))
+        })
+        .collect::<Vec<_>>();
+    // (最早可以在这个地区开局的小队的进入时间, 这个小队在这个地区的延迟, 地区名称)
+    let mut regions: Vec<(Instant, u64, &str)> = Vec::new();
+    for ((_name, player), &threshold) in matched.iter().zip(&thresholds) {
+        for (region, ms) in &player.latencies {
+            if *ms > threshold {
+                continue;
+            }
+            let key = (player.enqueued_at, *ms, region.as_str());
+            match regions.iter_mut().find(|(_, _, name)| name == region) {
+                Some(entry) => *entry = Ord::min(*entry, key),
+                None => regions.push(key),
+            }
+        }
+    }
+    regions.sort_unstable();
+    for (_, _, region) in regions {
+        let parties = matched
+            .iter()
+            .zip(&thresholds)
+            .filter(|((_name, player), &threshold)| player.accepts_region(region, threshold))
+            .map(|(party, _threshold)| party.clone())
+            .collect();
+        if let Some(group) = pick_group_in(arena, config, parties, conflicts) {
+            return Some(Group {
+                region: Some(region.to_string()),
+                ..group
+            });
+        }
+    }
+    // 没有发送延迟的小队可以在任何地区开局，它们之间不需要选地区
+    let parties = matched
+        .iter()
+        .filter(|(_name, player)| player.latencies.is_empty())
+        .cloned()
+        .collect();
+    pick_group_in(arena, config, parties, conflicts)
+}
+
+fn pick_group_in<T>(
+    arena: &str,
+    config: &ArenaConfig,
+    mut matched: Vec<(T, Player)>,
+    conflicts: &Conflicts<T>,
+) -> Option<Group<T>>
+where
+    T: Hash + Eq + Clone + Debug,
+{
+    let max_players = config.max_players;
+    // 等待时间长的玩家排在前面
+    matched.sort_by_key(|(_name, player)| player.enqueued_at);
+    // 等待最久的玩家等待超过fill_after以后，达到最少人数就可以开始，否则要等到最多人数
+    let need_players = match matched.first() {
+        Some((_name, oldest)) if oldest.enqueued_at.elapsed() >= config.fill_after => {
+            config.min_players
+        }
+        _ => max_players,
+    };
+    // 背包问题里排在前面的小队优先被选中
+    if let (Priority::SmallestSpread, Some((_name, oldest))) = (config.priority, matched.first()) {
+        let anchor = oldest.rank;
+        matched[1..].sort_by(|(_, a), (_, b)| {
+            f64::total_cmp(&(a.rank - anchor).abs(), &(b.rank - anchor).abs())
+        });
+    }
+    if let (Priority::DrawProbability, Some(draw), Some((_name, oldest))) =
+        (config.priority, config.draw, matched.first())
+    {
+        let anchor = oldest.clone();
+        matched[1..].sort_by(|(_, a), (_, b)| {
+            let quality = |player: &Player| {
+                rating::draw_quality(
+                    draw.beta,
+                    mean_and_deviation(&anchor),
+                    mean_and_deviation(player),
+                )
+            };
+            quality(b).total_cmp(&quality(a))
+        });
+    }
+    // 和排在前面的小队冲突的小队不参与这一局。背包问题不考虑冲突，所以先按优先级去掉冲突的小队
+    drop_conflicts(&mut matched, conflicts);
+    let num_matched: usize = matched.iter().map(|(_name, player)| player.length).sum();
+    if num_matched < need_players {
+        // 玩家不够
+        return None;
+    }
+    // 背包问题只保证人数，选中的小队不一定能分成人数相同的队伍（例如3人的小队放不进2人的队伍）。
+    // 分不了队时依次去掉选中的某个小队再选，去掉的小队少的方案先尝试，优先级低的小队先去掉
+    let mut queue = VecDeque::from([Vec::new()]);
+    let mut tried = HashSet::new();
+    let mut solved = 0;
+    let mut selected = None;
+    while let Some(excluded) = queue.pop_front() {
+        if solved >= SPLIT_RETRY_LIMIT {
+            break;
+        }
+        solved += 1;
+        let indices = (0..matched.len())
+            .filter(|i| !excluded.contains(i))
+            .collect::<Vec<_>>();
+        let players = indices.iter().map(|&i| &matched[i].1).collect::<Vec<_>>();
+        let Some((num_players, chosen)) = choose_parties(config, &players, need_players) else {
+            if excluded.is_empty() && config.roles.is_empty() {
+                println!("[匹配池] {} 中应当匹配 {} 至 {} 位玩家，但现有的小队无法匹配恰好这个玩家数的房间。这种情况比较罕见，服务器将在下一秒重试匹配算法。发生情况的玩家列表：{:?}",
+                arena, need_players, max_players, matched);
+            }
+            continue;
+        };
+        let chosen = chosen.into_iter().map(|k| indices[k]).collect::<Vec<_>>();
+        let parties = chosen
+            .iter()
+            .map(|&idx| matched[idx].clone())
+            .collect::<Vec<_>>();
+        if let Some(teams) = split_parties(config, &parties) {
+            selected = Some((parties, teams));
+            break;
+        }
+        if excluded.is_empty() {
+            println!("[匹配池] {} 中匹配到了 {} 位玩家，但现有的小队无法分成 {} 个人数相同的队伍，将去掉部分小队重试。发生情况的玩家列表：{:?}",
+            arena, num_players, config.num_teams, parties);
+        }
+        for &idx in chosen.iter().rev() {
+            let mut next = excluded.clone();
+            next.push(idx);
+            next.sort_unstable();
+            if tried.insert(next.clone()) {
+                queue.push_back(next);
+            }
+        }
+    }
+    let (parties, teams) = selected?;
+    // 预测的平局概率太低时不开局，等区间扩大或者要求降低以后再试
+    if let Some(draw) = config.draw {
+        let waited = parties
+            .iter()
+            .map(|(_name, player)| player.enqueued_at.elapsed())
+            .max()
+            .unwrap_or_default();
+        let quality = draw_quality(draw.beta, &parties, &teams, config.num_teams);
+        if quality < draw.min_quality_after(waited) {
+            return None;
+        }
+    }
+    // 每个队伍分别分配角色，分队时已经保证了能分配成功
+    let mut roles = Vec::new();
+    if !config.roles.is_empty() {
+        let members = parties
+            .iter()
+            .map(|(_name, player)| player.member_roles())
+            .collect::<Vec<_>>();
+        let team_slots = config.role_slots();
+        roles = vec![Vec::new(); parties.len()];
+        for t in 0..config.num_teams {
+            let team = (0..parties.len())
+                .filter(|&i| teams[i] == t)
+                .collect::<Vec<_>>();
+            let team_members = team
+                .iter()
+                .flat_map(|&i| members[i].iter().copied())
+                .collect::<Vec<_>>();
+            let mut assigned = role::assign_roles(&team_members, &team_slots)?.into_iter();
+            for &i in &team {
+                roles[i] = assigned.by_ref().take(members[i].len()).collect();
+            }
+        }
+    }
+    Some(Group {
+        parties,
+        teams,
+        roles,
+        region: None,
+        bots: Vec::new(),
+        attributes: Vec::new(),
+    })
+}
+
+// 分不了队时最多尝试多少种小队组合
+const SPLIT_RETRY_LIMIT: usize = 64;
+
+// 从按优先级排好的小队中选出一局的小队，返回(人数, 选中的小队下标)，选不出来时返回None
+fn choose_parties(
+    config: &ArenaConfig,
+    players: &[&Player],
+    need_players: usize,
+) -> Option<(usize, Vec<usize>)> {
+    if config.roles.is_empty() {
+        // 背包问题：找出人数在need_players和max_players之间、能平均分成num_teams个队伍的最多人数，
+        // 并且在这个人数的所有方案里尽量选择排在前面的小队
+        let lengths = players
+            .iter()
+            .map(|player| player.length)
+            .collect::<Vec<_>>();
+        knapsack::solve(&lengths, config.max_players, |sum| {
+            sum >= need_players && sum.is_multiple_of(config.num_teams)
+        })
+    } else {
+        // 区分角色时一局必须填满所有队伍的所有角色位置，人数固定为max_players。
+        // 角色凑不齐是常见情况（例如缺少治疗），等下一秒有新玩家加入或区间扩大以后再试
+        let members = players
+            .iter()
+            .map(|player| player.member_roles())
+            .collect::<Vec<_>>();
+        let chosen = role::choose_parties(&members, &config.role_slots(), config.num_teams)?;
+        Some((config.max_players, chosen))
+    }
+}
+
+// 分队，小队不拆开，各队伍总分尽量接近。区分角色时每个队伍的成员还要能填满这个队伍的角色位置
+fn split_parties<T>(config: &ArenaConfig, parties: &[(T, Player)]) -> Option<Vec<usize>> {
+    let lengths_and_ranks = parties
+        .iter()
+        .map(|(_name, player)| (player.length, player.rank))
+        .collect::<Vec<_>>();
+    let members = parties
+        .iter()
+        .map(|(_name, player)| player.member_roles())
+        .collect::<Vec<_>>();
+    let team_slots = config.role_slots();
+    let fits = |team: &[usize]| {
+        let team_members = team
+            .iter()
+            .flat_map(|&i| members[i].iter().copied())
+            .collect::<Vec<_>>();
+        config.roles.is_empty() || role::assign_roles(&team_members, &team_slots).is_some()
+    };
+    team::split_teams(&lengths_and_ranks, config.num_teams, &fits)
+}
+
+// 按优先级从前往后，去掉和前面的小队冲突的小队
+fn drop_conflicts<T>(parties: &mut Vec<(T, Player)>, conflicts: &Conflicts<T>)
+where
+    T: Hash + Eq + Clone,
+{
+    let mut kept: Vec<T> = Vec::new();
+    parties.retain(|(id, _player)| {
+        let ok = kept.iter().all(|other| !conflicts.conflicts(id, other));
+        if ok {
+            kept.push(id.clone());
+        }
+        ok
+    });
+}
+
+// 为已经开始的房间补位：从和房间互相接受、能接受房间属性的小队中，选出恰好填满每个队伍空位的小队，选不出来时返回None
+// 返回的teams是小队补到的队伍编号。等待时间长的小队优先，匹配池不按等待时间优先选择时，分数离房间近的小队优先
+pub fn pick_backfill<T>(
+    config: &ArenaConfig,
+    backfill: &Backfill,
+    parties: &[(T, Player)],
+    conflicts: &Conflicts<T>,
+) -> Option<Group<T>>
+where
+    T: Hash + Eq + Clone,
+{
+    let now = Instant::now();
+    // 按匹配池的属性规则排列房间的属性，房间没有发送的属性为None
+    let assignment = config
+        .attributes
+        .iter()
+        .map(|rule| {
+            let value = backfill
+                .attributes
+                .iter()
+                .find(|(key, _value)| key == &rule.key);
+            value.map(|(_key, value)| value.clone())
+        })
+        .collect::<attribute::Assignment>();
+    let mut candidates = parties
+        .iter()
+        .filter(|(_name, player)| {
+            backfill.accepts(player)
+                && attribute::accepts(&config.attributes, &assignment, player, now)
+                && match (config.latency, &backfill.region) {
+                    (Some(latency), Some(region)) => {
+                        let waited = now.saturating_duration_since(player.enqueued_at);
+                        player.accepts_region(region, latency.threshold_after(waited))
+                    }
+                    _ => true,
+                }
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_name, player)| player.enqueued_at);
+    if !matches!(config.priority, Priority::LongestWait) {
+        let anchor = backfill.anchor.rank;
+        candidates.sort_by(|(_, a), (_, b)| {
+            f64::total_cmp(&(a.rank - anchor).abs(), &(b.rank - anchor).abs())
+        });
+    }
+    drop_conflicts(&mut candidates, conflicts);
+    let lengths = candidates
+        .iter()
+        .map(|(_name, player)| player.length)
+        .collect::<Vec<_>>();
+    let mut fill = Fill {
+        lengths: &lengths,
+        open: backfill.open_slots.clone(),
+        teams: vec![None; lengths.len()],
+        visited: 0,
+    };
+    if !fill.dfs(0) {
+        return None;
+    }
+    let mut group = Group {
+        parties: Vec::new(),
+        teams: Vec::new(),
+        roles: Vec::new(),
+        region: backfill.region.clone(),
+        bots: Vec::new(),
+        attributes: backfill.attributes.clone(),
+    };
+    for (party, team) in candidates.into_iter().zip(fill.teams) {
+        if let Some(team) = team {
+            group.parties.push(party);
+            group.teams.push(team);
+        }
+    }
+    Some(group)
+}
+
+// 用机器人补满一局：等待超过bots.after的小队按等待时间依次作为中心，从和它互相接受的小队中按等待时间选出不超过一局人数的小队，
+// 真人至少有bots.min_players位时，剩下的位置用分数为真人平均分的机器人补满，再一起分队。
+// 匹配池区分角色时不使用机器人。真人已经够一局时不使用机器人，这时应当由普通的匹配策略决定能否开局
+pub fn pick_bot_group<T>(
+    config: &ArenaConfig,
+    parties: &[(T, Player)],
+    conflicts: &Conflicts<T>,
+) -> Option<Group<T>>
+where
+    T: Hash + Eq + Clone,
+{
+    let bots = config.bots?;
+    if !config.roles.is_empty() {
+        return None;
+    }
+    let now = Instant::now();
+    let mut sorted = parties.to_vec();
+    sorted.sort_by_key(|(_name, player)| player.enqueued_at);
+    sorted
+        .iter()
+        .take_while(|(_name, player)| {
+            now.saturating_duration_since(player.enqueued_at) >= bots.after
+        })
+        .enumerate()
+        .find_map(|(k, (_name, anchor))| {
+            // 中心的小队想要的属性组合依次尝试
+            let assignments = attribute::assignments(&config.attributes, [anchor], now);
+            assignments.into_iter().find_map(|assignment| {
+                let group =
+                    bot_group_around(config, bots, k, &sorted, conflicts, &assignment, now)?;
+                Some(Group {
+                    attributes: attribute::agreed(&config.attributes, &assignment),
+                    ..group
+                })
+            })
+        })
+}
+
+// 以sorted[k]为中心、按assignment的属性组合用机器人补满一局，sorted已经按等待时间排好
+fn bot_group_around<T>(
+    config: &ArenaConfig,
+    bots: BotFill,
+    k: usize,
+    sorted: &[(T, Player)],
+    conflicts: &Conflicts<T>,
+    assignment: &attribute::Assignment,
+    now: Instant,
+) -> Option<Group<T>>
+where
+    T: Hash + Eq + Clone,
+{
+    let anchor = &sorted[k].1;
+    // 中心的小队排在第一个，冲突时留下它
+    let others = sorted[..k].iter().chain(&sorted[k + 1..]);
+    let mut candidates = std::iter::once(&sorted[k])
+        .chain(others)
+        .filter(|(_name, player)| {
+            player.rank_min() <= anchor.rank
+                && anchor.rank <= player.rank_max()
+                && anchor.rank_min() <= player.rank
+                && player.rank <= anchor.rank_max()
+                && attribute::accepts(&config.attributes, assignment, player, now)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    // 区分地区时在中心的小队延迟最低的地区开局，没有发送延迟的小队只和同样没有发送延迟的小队一起开局
+    let mut region = None;
+    if let Some(latency) = config.latency {
+        let threshold = |player: &Player| {
+            latency.threshold_after(now.saturating_duration_since(player.enqueued_at))
+        };
+        if anchor.latencies.is_empty() {
+            candidates.retain(|(_name, player)| player.latencies.is_empty());
+        } else {
+            let (name, _ms) = anchor
+                .latencies
+                .iter()
+                .filter(|(_region, ms)| *ms <= threshold(anchor))
+                .min_by_key(|(_region, ms)| *ms)?;
+            candidates.retain(|(_name, player)| player.accepts_region(name, threshold(player)));
+            region = Some(name.clone());
+        }
+    }
+    drop_conflicts(&mut candidates, conflicts);
+    // 互相接受模式下，选中的真人之间也要互相接受，中心的小队离自己的分数最近，一定会留下
+    if config.mutual {
+        let indexed = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, (_name, player))| (i, player.clone()))
+            .collect::<Vec<_>>();
+        let kept = arena::mutual_accepted(indexed, anchor.rank)
+            .into_iter()
+            .map(|(i, _player)| i)
+            .collect::<HashSet<_>>();
+        let mut i = 0;
+        candidates.retain(|_party| {
+            i += 1;
+            kept.contains(&(i - 1))
+        });
+    }
+    // 真人和机器人加起来要能平均分成num_teams个队伍
+    let max_players = config.max_players / config.num_teams * config.num_teams;
+    let mut chosen = Vec::new();
+    let mut num_players = 0;
+    for party in candidates {
+        if num_players + party.1.length <= max_players {
+            num_players += party.1.length;
+            chosen.push(party);
+        }
+    }
+    let num_bots = max_players - num_players;
+    if num_players < bots.min_players || num_bots == 0 {
+        return None;
+    }
+    let rank = chosen
+        .iter()
+        .map(|(_name, player)| player.rank * player.length as f64)
+        .sum::<f64>()
+        / num_players as f64;
+    let lengths_and_ranks = chosen
+        .iter()
+        .map(|(_name, player)| (player.length, player.rank))
+        .chain(std::iter::repeat_n((1, rank), num_bots))
+        .collect::<Vec<_>>();
+    let teams = team::split_teams(&lengths_and_ranks, config.num_teams, &|_team| true)?;
+    // 机器人的分数是真人的平均分，评分偏差按0计算
+    if let Some(draw) = config.draw {
+        let waited = chosen
+            .iter()
+            .map(|(_name, player)| now.saturating_duration_since(player.enqueued_at))
+            .max()
+            .unwrap_or_default();
+        let members = chosen
+            .iter()
+            .map(|(_name, player)| (player.rank, player.deviation, player.length))
+            .chain(std::iter::repeat_n((rank, 0.0, 1), num_bots))
+            .collect::<Vec<_>>();
+        let quality = sides_quality(draw.beta, &members, &teams, config.num_teams);
+        if quality < draw.min_quality_after(waited) {
+            return None;
+        }
+    }
+    let (teams, bot_teams) = teams.split_at(chosen.len());
+    Some(Group {
+        parties: chosen,
+        teams: teams.to_vec(),
+        roles: Vec::new(),
+        region,
+        bots: bot_teams.iter().map(|&team| Bot { team, rank }).collect(),
+        attributes: Vec::new(),
+    })
+}
+
+// 按优先级从前往后决定每个小队补到哪个队伍或者不选，先找到的方案就是优先级高的小队尽量被选中的方案。
+// 搜索超过节点数上限时当作补不满
+struct Fill<'a> {
+    lengths: &'a [usize],
+    // 每个队伍还剩几个空位
+    open: Vec<usize>,
+    teams: Vec<Option<usize>>,
+    visited: usize,
+}
+
+impl<'a> Fill<'a> {
+    fn dfs(&mut self, k: usize) -> bool {
+        self.visited += 1;
+        if self.visited > team::SEARCH_LIMIT {
+            return false;
+        }
+        let open = self.open.iter().sum::<usize>();
+        if open == 0 {
+            return true;
+        }
+        // 剩下的小队加起来也填不满
+        if open > self.lengths[k..].iter().sum::<usize>() {
+            return false;
+        }
+        let length = self.lengths[k];
+        for t in 0..self.open.len() {
+            if length == 0 || self.open[t] < length {
+                continue;
+            }
+            self.open[t] -= length;
+            self.teams[k] = Some(t);
+            if self.dfs(k + 1) {
+                return true;
+            }
+            self.open[t] += length;
+            self.teams[k] = None;
+        }
+        self.dfs(k + 1)
+    }
+}
+
+fn mean_and_deviation(player: &Player) -> (f64, f64) {
+    (player.rank, player.deviation)
+}
+
+// 一局预测的平局概率：分队时取每两个队伍之间平局概率的最小值，不分队时取每两个小队之间的最小值
+fn draw_quality<T>(beta: f64, parties: &[(T, Player)], teams: &[usize], num_teams: usize) -> f64 {
+    let members = parties
+        .iter()
+        .map(|(_name, player)| (player.rank, player.deviation, player.length))
+        .collect::<Vec<_>>();
+    sides_quality(beta, &members, teams, num_teams)
+}
+
+// members中每一项是小队的(分数, 评分偏差, 人数)，和teams一一对应
+fn sides_quality(
+    beta: f64,
+    members: &[(f64, f64, usize)],
+    teams: &[usize],
+    num_teams: usize,
+) -> f64 {
+    let sides = if num_teams > 1 {
+        (0..num_teams)
+            .map(|t| {
+                (0..members.len())
+                    .filter(|&i| teams[i] == t)
+                    .map(|i| members[i])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    } else {
+        // 不分队时每个小队各自为战，小队按一位玩家计算
+        members
+            .iter()
+            .map(|&(rank, deviation, _length)| vec![(rank, deviation, 1)])
+            .collect::<Vec<_>>()
+    };
+    let mut ans = 1.0;
+    for a in 0..sides.len() {
+        for b in a + 1..sides.len() {
+            ans = f64::min(ans, rating::team_draw_quality(beta, &sides[a], &sides[b]));
+        }
+    }
+    ans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{config, player};
+    use std::time::Duration;
+
+    fn names<T: Clone + Ord>(group: &Group<T>) -> Vec<T> {
+        let mut names = group
+            .parties
+            .iter()
+            .map(|(name, _player)| name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn retries_when_split_fails() {
+        // 背包问题先选中3+1，但3人的小队放不进2人的队伍，应当改选2+2
+        let parties = vec![
+            ("a", player(1000.0, 100.0, 3, 40)),
+            ("b", player(1000.0, 100.0, 1, 30)),
+            ("c", player(1000.0, 100.0, 2, 20)),
+            ("d", player(1000.0, 100.0, 2, 10)),
+        ];
+        let groups = RankStrategy.form_groups("test", &config(4, 2), parties, &Conflicts::new());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(names(&groups[0]), vec!["c", "d"]);
+        assert_ne!(groups[0].teams[0], groups[0].teams[1]);
+    }
+
+    #[test]
+    fn skips_point_that_cannot_form_group() {
+        // a1、a2、a3互相屏蔽，覆盖人数最多的分数组不成一局，b1和b2仍然应当匹配成功
+        let parties = vec![
+            ("a1", player(1000.0, 100.0, 1, 50)),
+            ("a2", player(1000.0, 100.0, 1, 40)),
+            ("a3", player(1000.0, 100.0, 1, 30)),
+            ("b1", player(5000.0, 100.0, 1, 20)),
+            ("b2", player(5000.0, 100.0, 1, 10)),
+        ];
+        let mut conflicts = Conflicts::new();
+        conflicts.add(&"a1", &"a2");
+        conflicts.add(&"a1", &"a3");
+        conflicts.add(&"a2", &"a3");
+        let groups = RankStrategy.form_groups("test", &config(2, 1), parties, &conflicts);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(names(&groups[0]), vec!["b1", "b2"]);
+    }
+
+    fn bot_config(max_players: usize, num_teams: usize) -> ArenaConfig {
+        ArenaConfig {
+            bots: Some(BotFill {
+                after: Duration::from_secs(10),
+                min_players: 1,
+            }),
+            ..config(max_players, num_teams)
+        }
+    }
+
+    #[test]
+    fn bots_only_fill_missing_players() {
+        let parties = vec![
+            ("a", player(1000.0, 100.0, 1, 60)),
+            ("b", player(1000.0, 100.0, 1, 50)),
+        ];
+        let conflicts = Conflicts::new();
+        let group = pick_bot_group(&bot_config(4, 2), &parties, &conflicts).unwrap();
+        assert_eq!(names(&group), vec!["a", "b"]);
+        assert_eq!(group.bots.len(), 2);
+        // 真人已经够一局时不用机器人
+        assert!(pick_bot_group(&bot_config(2, 2), &parties, &conflicts).is_none());
+    }
+
+    #[test]
+    fn bots_respect_mutual_and_draw() {
+        // b和c都接受a，但b和c互相不接受
+        let parties = vec![
+            ("a", player(1000.0, 100.0, 1, 60)),
+            ("b", player(920.0, 100.0, 1, 50)),
+            ("c", player(1080.0, 100.0, 1, 40)),
+        ];
+        let conflicts = Conflicts::new();
+        let mutual = ArenaConfig {
+            mutual: true,
+            ..bot_config(4, 1)
+        };
+        let group = pick_bot_group(&mutual, &parties, &conflicts).unwrap();
+        assert_eq!(group.parties.len(), 2);
+        assert_eq!(group.bots.len(), 2);
+        // 评分偏差很大的玩家和机器人的平局概率达不到要求时不开局
+        let mut uncertain = player(1000.0, 100.0, 1, 60);
+        uncertain.deviation = 100.0;
+        let parties = vec![("a", uncertain)];
+        let strict = ArenaConfig {
+            draw: Some(arena::DrawCriterion {
+                beta: 1.0,
+                min_quality: 0.5,
+                relax: Duration::ZERO,
+            }),
+            ..bot_config(2, 2)
+        };
+        assert!(pick_bot_group(&bot_config(2, 2), &parties, &conflicts).is_some());
+        assert!(pick_bot_group(&strict, &parties, &conflicts).is_none());
+    }
+
+    #[test]
+    fn backfill_respects_attributes() {
+        let config = ArenaConfig {
+            attributes: vec![attribute::AttributeRule {
+                key: "map".to_string(),
+                kind: attribute::AttributeKind::Exact,
+            }],
+            ..config(4, 2)
+        };
+        let with_map = |map: &str| {
+            let mut player = player(1000.0, 100.0, 1, 10);
+            player.attributes = vec![("map".to_string(), vec![map.to_string()])];
+            player
+        };
+        let parties = vec![("a", with_map("m1")), ("b", with_map("m2"))];
+        let backfill = Backfill {
+            anchor: player(1000.0, 100.0, 0, 0),
+            open_slots: vec![1, 0],
+            region: None,
+            attributes: vec![("map".to_string(), "m2".to_string())],
+            sender: "127.0.0.1:1".parse().unwrap(),
+        };
+        let group = pick_backfill(&config, &backfill, &parties, &Conflicts::new()).unwrap();
+        assert_eq!(names(&group), vec!["b"]);
+    }
+}
@@ -0,0 +1,175 @@
+// 分队算法：把已经匹配成功的小队分成人数相同的若干个队伍，小队不拆开，并且让各队伍的总分尽量接近
+
+// 把小队逐个放进队伍的搜索（分队、区分角色时的分队和补位）共用的节点数上限
+pub const SEARCH_LIMIT: usize = 10_000;
+
+// 依次尝试把小队放进teams中的队伍。空队伍之间没有区别，只保留其中第一个
+pub fn distinct_teams(
+    teams: impl IntoIterator<Item = usize>,
+    is_empty: impl Fn(usize) -> bool,
+) -> Vec<usize> {
+    let mut tried_empty = false;
+    teams
+        .into_iter()
+        .filter(|&t| !is_empty(t) || !std::mem::replace(&mut tried_empty, true))
+        .collect()
+}
+
+// parties中每一项是(小队人数, 小队分数)，分数可以是负数或小数。小队的总分按 分数 × 人数 计算
+// 返回每个小队分到的队伍编号（从0开始）；无法恰好分成num_teams个人数相同的队伍时返回None。
+// 搜索超过节点数上限时返回已经找到的最好结果（可能一个都没找到）
+// 每个队伍的小队还要满足fits，例如能填满这个队伍的所有角色位置。fits的参数是分到同一个队伍的小队下标，
+// 队伍还没有分完时也会调用，用来提前排除不可能的分法
+pub fn split_teams(
+    parties: &[(usize, f64)],
+    num_teams: usize,
+    fits: &dyn Fn(&[usize]) -> bool,
+) -> Option<Vec<usize>> {
+    if num_teams <= 1 {
+        let team = (0..parties.len()).collect::<Vec<_>>();
+        return fits(&team).then(|| vec![0; parties.len()]);
+    }
+    let total: usize = parties.iter().map(|&(length, _rank)| length).sum();
+    if !total.is_multiple_of(num_teams) {
+        return None;
+    }
+    let mut search = Search {
+        parties,
+        // 人数多的小队先放，总分高的先放，这样先找到的结果就比较均衡
+        order: {
+            let mut order = (0..parties.len()).collect::<Vec<_>>();
+            order.sort_by(|&i, &j| {
+                let score = |(length, rank): (usize, f64)| (length, rank * length as f64);
+                let ((length_i, score_i), (length_j, score_j)) =
+                    (score(parties[i]), score(parties[j]));
+                length_j.cmp(&length_i).then(score_j.total_cmp(&score_i))
+            });
+            order
+        },
+        team_size: total / num_teams,
+        capacity: vec![total / num_teams; num_teams],
+        totals: vec![0.0; num_teams],
+        current: vec![0; parties.len()],
+        members: vec![Vec::new(); num_teams],
+        fits,
+        best: None,
+        best_diff: f64::INFINITY,
+        visited: 0,
+    };
+    search.dfs(0);
+    search.best
+}
+
+struct Search<'a> {
+    parties: &'a [(usize, f64)],
+    order: Vec<usize>,
+    // 每个队伍的人数
+    team_size: usize,
+    // 每个队伍还剩几个空位
+    capacity: Vec<usize>,
+    // 每个队伍的当前总分
+    totals: Vec<f64>,
+    current: Vec<usize>,
+    // 每个队伍当前有哪些小队
+    members: Vec<Vec<usize>>,
+    fits: &'a dyn Fn(&[usize]) -> bool,
+    best: Option<Vec<usize>>,
+    best_diff: f64,
+    visited: usize,
+}
+
+impl<'a> Search<'a> {
+    fn dfs(&mut self, k: usize) {
+        self.visited += 1;
+        if self.best_diff == 0.0 || self.visited > SEARCH_LIMIT {
+            return;
+        }
+        if k == self.order.len() {
+            let max = self
+                .totals
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            let min = self.totals.iter().copied().fold(f64::INFINITY, f64::min);
+            if max - min < self.best_diff {
+                self.best_diff = max - min;
+                self.best = Some(self.current.clone());
+            }
+            return;
+        }
+        let idx = self.order[k];
+        let (length, rank) = self.parties[idx];
+        let score = rank * length as f64;
+        // 先尝试总分最低的队伍
+        let mut teams = (0..self.totals.len()).collect::<Vec<_>>();
+        teams.sort_by(|&a, &b| self.totals[a].total_cmp(&self.totals[b]));
+        let teams = distinct_teams(teams, |t| self.capacity[t] == self.team_size);
+        for t in teams {
+            if self.capacity[t] < length {
+                continue;
+            }
+            self.members[t].push(idx);
+            if (self.fits)(&self.members[t]) {
+                // 浮点数加减以后不一定能恢复原值，所以直接保存原来的总分
+                let total = self.totals[t];
+                self.capacity[t] -= length;
+                self.totals[t] += score;
+                self.current[idx] = t;
+                self.dfs(k + 1);
+                self.capacity[t] += length;
+                self.totals[t] = total;
+            }
+            self.members[t].pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn team_scores(parties: &[(usize, f64)], teams: &[usize], num_teams: usize) -> Vec<f64> {
+        let mut scores = vec![0.0; num_teams];
+        for (&(length, rank), &t) in parties.iter().zip(teams) {
+            scores[t] += rank * length as f64;
+        }
+        scores
+    }
+
+    #[test]
+    fn split_balances_totals() {
+        let parties = [(1, 1000.0), (1, 1200.0), (1, 1100.0), (1, 1100.0)];
+        let teams = split_teams(&parties, 2, &|_team| true).unwrap();
+        assert_eq!(team_scores(&parties, &teams, 2), vec![2200.0, 2200.0]);
+    }
+
+    #[test]
+    fn split_keeps_parties_together() {
+        // 2人的小队只能和两个1人的小队分到不同的队伍
+        let parties = [(2, 1000.0), (1, 900.0), (1, 1100.0)];
+        let teams = split_teams(&parties, 2, &|_team| true).unwrap();
+        assert_ne!(teams[0], teams[1]);
+        assert_eq!(teams[1], teams[2]);
+    }
+
+    #[test]
+    fn split_impossible() {
+        // 3人的小队放不进2人的队伍
+        assert_eq!(split_teams(&[(3, 0.0), (1, 0.0)], 2, &|_team| true), None);
+        // 总人数不能平均分
+        assert_eq!(split_teams(&[(1, 0.0), (2, 0.0)], 2, &|_team| true), None);
+        // 2+2+2分不成两个3人的队伍
+        let parties = [(2, 0.0), (2, 0.0), (2, 0.0)];
+        assert_eq!(split_teams(&parties, 2, &|_team| true), None);
+    }
+
+    #[test]
+    fn split_respects_fits() {
+        // 第0个和第1个小队不能在同一个队伍
+        let fits = |team: &[usize]| !(team.contains(&0) && team.contains(&1));
+        let parties = [(1, 1000.0), (1, 1000.0), (1, 0.0), (1, 0.0)];
+        let teams = split_teams(&parties, 2, &fits).unwrap();
+        assert_ne!(teams[0], teams[1]);
+        assert_eq!(split_teams(&parties[..2], 1, &fits), None);
+    }
+}
@@ -0,0 +1,60 @@
+// 测试用的小队和匹配池配置，各个模块的测试共用
+use crate::arena::{ArenaConfig, Expansion, Player, Priority};
+use std::time::{Duration, Instant};
+
+// 分数为rank、区间半径为rank_diff、人数为length、已经等待了waited秒的小队
+pub fn player(rank: f64, rank_diff: f64, length: usize, waited: u64) -> Player {
+    let enqueued_at = Instant::now() - Duration::from_secs(waited);
+    Player {
+        rank,
+        init_rank_diff: rank_diff,
+        deviation: 0.0,
+        rank_diff,
+        length,
+        speed: 0.0,
+        enqueued_at,
+        expanding_since: enqueued_at,
+        roles: Vec::new(),
+        latencies: Vec::new(),
+        attributes: Vec::new(),
+    }
+}
+
+// 给小队设置可以接受的属性取值，(属性名称, 取值)
+pub fn with_attributes(player: Player, attributes: &[(&str, &[&str])]) -> Player {
+    let attributes = attributes
+        .iter()
+        .map(|(key, values)| {
+            (
+                key.to_string(),
+                values.iter().map(|v| v.to_string()).collect(),
+            )
+        })
+        .collect();
+    Player {
+        attributes,
+        ..player
+    }
+}
+
+// 每局正好max_players人、不使用其他规则的匹配池
+pub fn config(max_players: usize, num_teams: usize) -> ArenaConfig {
+    ArenaConfig {
+        min_players: max_players,
+        max_players,
+        fill_after: Duration::from_secs(3600),
+        num_teams,
+        mutual: false,
+        expansion: Expansion::Linear,
+        max_rank_diff: None,
+        priority: Priority::LongestWait,
+        roles: Vec::new(),
+        latency: None,
+        recent_cooldown: Duration::ZERO,
+        rating: None,
+        draw: None,
+        accept_timeout: None,
+        bots: None,
+        attributes: Vec::new(),
+    }
+}
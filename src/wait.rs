@@ -0,0 +1,92 @@
+// 等待时间估计：按分数段记录匹配池最近匹配成功的小队等了多久，用来估计排队中的小队还要等多久
+use std::collections::{HashMap, VecDeque};
+
+// 分数段的宽度
+pub const BAND_WIDTH: f64 = 100.0;
+
+// 每个分数段保留最近多少条记录
+pub const HISTORY_LEN: usize = 50;
+
+// 分数段的记录少于这么多条时，连同相邻的分数段一起估计，还不够就用整个匹配池的记录
+const MIN_SAMPLES: usize = 5;
+
+// 一个匹配池的等待记录
+#[derive(Debug, Default)]
+pub struct WaitHistory {
+    // 分数段 => 最近匹配成功的小队等待的秒数，旧的在前
+    bands: HashMap<i64, VecDeque<u64>>,
+    // 整个匹配池最近的记录
+    all: VecDeque<u64>,
+}
+
+fn band(rank: f64) -> i64 {
+    (rank / BAND_WIDTH).floor() as i64
+}
+
+fn push(history: &mut VecDeque<u64>, wait_secs: u64) {
+    if history.len() >= HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(wait_secs);
+}
+
+impl WaitHistory {
+    // 分数为rank的小队等待了wait_secs秒以后匹配成功
+    pub fn record(&mut self, rank: f64, wait_secs: u64) {
+        push(self.bands.entry(band(rank)).or_default(), wait_secs);
+        push(&mut self.all, wait_secs);
+    }
+
+    // 估计分数为rank、已经等待了wait_secs秒的小队还要等多少秒，记录不够时返回None。
+    // 只看等待时间不短于wait_secs的记录，取中位数减去已经等待的时间，至少为1秒
+    pub fn estimate(&self, rank: f64, wait_secs: u64) -> Option<u64> {
+        let band = band(rank);
+        let own = self.bands.get(&band).into_iter().flatten();
+        let mut samples = own.copied().collect::<Vec<_>>();
+        if samples.len() < MIN_SAMPLES {
+            let neighbors = [band - 1, band + 1]
+                .into_iter()
+                .filter_map(|other| self.bands.get(&other))
+                .flatten();
+            samples.extend(neighbors);
+        }
+        if samples.len() < MIN_SAMPLES {
+            samples = self.all.iter().copied().collect();
+        }
+        samples.retain(|&secs| secs >= wait_secs);
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let median = samples[samples.len() / 2];
+        Some(u64::max(median - wait_secs, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_from_own_band() {
+        let mut history = WaitHistory::default();
+        for secs in [10, 20, 30, 40, 50] {
+            history.record(1050.0, secs);
+        }
+        history.record(3000.0, 500);
+        assert_eq!(history.estimate(1020.0, 0), Some(30));
+        // 已经等了25秒，只看等待更久的记录
+        assert_eq!(history.estimate(1020.0, 25), Some(15));
+        assert_eq!(history.estimate(1020.0, 60), None);
+    }
+
+    #[test]
+    fn estimate_falls_back_to_arena() {
+        let mut history = WaitHistory::default();
+        assert_eq!(history.estimate(0.0, 0), None);
+        history.record(-250.0, 8);
+        history.record(5000.0, 12);
+        history.record(5000.0, 90);
+        assert_eq!(history.estimate(0.0, 0), Some(12));
+    }
+}